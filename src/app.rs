@@ -1,47 +1,611 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::f32::consts::PI;
 
 use eframe::egui::Vec2;
-use eframe::egui_wgpu;
 use eframe::{egui, Frame};
 
-use crate::gpu;
+use rt_shader::gpu::{self, DisplaySettings};
+use rt_shader::ray_tracer::camera::Camera;
+use rt_shader::ray_tracer::scene::{Instance, Scene, Sphere};
+use rt_shader::ray_tracer::transform::Transform;
+use rt_shader::ray_tracer::vectors::{Point3, Vec3};
+use rt_shader::{RayTracerWidget, ViewSettings};
+
+/// radians per point dragged
+const ROTATION_SPEED: f32 = 0.01;
+/// how fast scrolling zooms, the distance is multiplied by exp(-scroll * ZOOM_SPEED)
+const ZOOM_SPEED: f32 = 0.002;
+/// units per second, FAST_FLY_SPEED is with shift held
+const FLY_SPEED: f32 = 2.0;
+const FAST_FLY_SPEED: f32 = 10.0;
+
+/**
+ * A view of the scene in its own window, with a camera that isn't saved with the scene
+ */
+struct ExtraView {
+    id: egui::Id,
+    title: String,
+    camera: Camera,
+    display: DisplaySettings,
+    /// false once the window is closed, the view is removed then
+    open: bool,
+}
 
 pub struct AppUI {
-    render_time: Arc<AtomicU64>,
+    ray_tracer: RayTracerWidget,
+    /// the percentage of the physical pixels of the views that are rendered
+    render_scale: u32,
+    /// the display settings of the view behind the windows, the one of the camera of the scene
+    display: DisplaySettings,
+    views: Vec<ExtraView>,
+    /// the number of views created so far, for their ids
+    view_count: u32,
+    scene: Scene,
+    /// the path typed in the "Open scene" field and the error of the last attempt to open it
+    scene_path: String,
+    scene_error: Option<String>,
+    /// the path typed in the "Save render" field and what happened the last time it was saved
+    render_path: String,
+    render_saved: Option<Result<String, String>>,
 }
 
 impl AppUI {
-    pub fn new(eframe_context: &eframe::CreationContext) -> Self {
+    pub fn new(eframe_context: &eframe::CreationContext, scene: Scene) -> Self {
         let wgpu_render_state = eframe_context.wgpu_render_state.as_ref().unwrap();
-        let resources = gpu::get_render_resources(wgpu_render_state);
-        wgpu_render_state
-            .renderer
-            .write()
-            .callback_resources
-            .insert(resources);
-        return AppUI {
-            render_time: Arc::new(AtomicU64::new(f64::NAN.to_bits())),
-        };
+        AppUI {
+            ray_tracer: RayTracerWidget::new(wgpu_render_state, &scene),
+            render_scale: 100,
+            display: DisplaySettings::default(),
+            views: Vec::new(),
+            view_count: 0,
+            scene,
+            scene_path: String::new(),
+            scene_error: None,
+            render_path: String::from("render.exr"),
+            render_saved: None,
+        }
+    }
+
+    /**
+     * Has to be called after modifying the scene so the gpu gets the new version
+     */
+    fn scene_changed(&mut self) {
+        self.scene.rebuild_bvh();
+        self.ray_tracer.set_scene(&self.scene);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_scene_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.scene_path)
+                .on_hover_text("Path to a .ron or .json scene file or to an .obj mesh");
+            let path = std::path::Path::new(self.scene_path.trim());
+            let is_mesh = path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("obj"));
+
+            if is_mesh && ui.button("Add mesh").clicked() {
+                match rt_shader::ray_tracer::obj_import::load_obj(path, &mut self.scene.materials) {
+                    Ok(mesh) => {
+                        self.scene.add_mesh(mesh);
+                        self.scene_error = None;
+                        self.scene_changed();
+                    }
+                    Err(error) => self.scene_error = Some(format!("{}: {}", path.display(), error)),
+                }
+            } else if !is_mesh && ui.button("Open scene").clicked() {
+                match rt_shader::ray_tracer::scene_file::load_scene(path) {
+                    Ok(scene) => {
+                        self.scene = scene;
+                        self.scene_error = None;
+                        self.scene_changed();
+                    }
+                    Err(error) => self.scene_error = Some(error.to_string()),
+                }
+            }
+        });
+        if let Some(error) = &self.scene_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        ui.separator();
+    }
+
+    /**
+     * Saves the image on the gpu, the format comes from the extension of the path
+     */
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.render_path)
+                .on_hover_text(
+                    "Path to a .png, .exr or .pfm file, the last two keep the high dynamic range",
+                );
+            if ui.button("Save render…").clicked() {
+                let path = std::path::Path::new(self.render_path.trim());
+                self.render_saved = Some(
+                    self.ray_tracer
+                        .save_render(main_view_id(), path)
+                        .map(|()| format!("Saved {}", path.display())),
+                );
+            }
+        });
+        match &self.render_saved {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(error)) => {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            None => {}
+        }
     }
 
     fn ray_tracer_ui(&mut self, ui: &mut egui::Ui) -> Vec2 {
         let size = ui.available_size();
-        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::focusable_noninteractive());
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        // the camera can't be borrowed from the scene while the scene casts rays
+        let mut camera = self.scene.camera;
+        camera_controls(ui, &response, &mut camera, &self.scene);
+        self.scene.camera = camera;
 
-        ui.painter().add(egui_wgpu::Callback::new_paint_callback(
+        self.ray_tracer.paint(
+            ui,
             rect,
-            gpu::RenderCallBack {
-                render_time: self.render_time.clone(),
-                output_size: size,
+            main_view_id(),
+            &ViewSettings {
+                camera,
+                render_scale: self.render_scale as f32 / 100.0,
+                display: self.display,
+                samples_target: self.scene.settings.samples_per_pixel,
             },
-        ));
+        );
         size
     }
+
+    /**
+     * Opens a window with another view of the scene
+     */
+    fn add_view(&mut self, title: &str, camera: Camera) {
+        self.view_count += 1;
+        self.views.push(ExtraView {
+            id: egui::Id::new("view").with(self.view_count),
+            title: format!("{} {}", title, self.view_count),
+            camera,
+            display: self.display,
+            open: true,
+        });
+    }
+
+    /**
+     * The windows of the other views, each one has its own camera controls and display settings
+     */
+    fn extra_views_ui(&mut self, context: &egui::Context) {
+        let render_scale = self.render_scale as f32 / 100.0;
+        for view in &mut self.views {
+            egui::Window::new(&view.title)
+                .id(view.id)
+                .open(&mut view.open)
+                .default_size((320.0, 240.0))
+                .resizable(true)
+                .show(context, |ui| {
+                    ui.horizontal(|ui| {
+                        display_ui(ui, &mut view.display);
+                        ui.label(format!(
+                            "Samples: {} / {}",
+                            self.ray_tracer.sample_count(view.id),
+                            self.scene.settings.samples_per_pixel
+                        ));
+                    });
+                    let (rect, response) =
+                        ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
+                    camera_controls(ui, &response, &mut view.camera, &self.scene);
+                    self.ray_tracer.paint(
+                        ui,
+                        rect,
+                        view.id,
+                        &ViewSettings {
+                            camera: view.camera,
+                            render_scale,
+                            display: view.display,
+                            samples_target: self.scene.settings.samples_per_pixel,
+                        },
+                    );
+                });
+        }
+        let ray_tracer = &mut self.ray_tracer;
+        self.views.retain(|view| {
+            if !view.open {
+                ray_tracer.remove_view(view.id);
+            }
+            view.open
+        });
+    }
+
+    fn scene_ui(&mut self, ui: &mut egui::Ui) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.open_scene_ui(ui);
+
+        let scene = &mut self.scene;
+        let mut changed = false;
+        let mut removed = None;
+        let mut removed_mesh = None;
+        let mut added_instance = None;
+        let mut removed_instance = None;
+        let last_material = scene.materials.len() as u32 - 1;
+
+        let camera = &mut scene.camera;
+        ui.label(format!(
+            "Camera at {:?} looking at {:?} with a {}° field of view",
+            <[f32; 3]>::from(camera.position),
+            <[f32; 3]>::from(camera.look_at),
+            camera.vertical_fov
+        ));
+        // the camera isn't part of the bvh, changing it doesn't need scene_changed
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut camera.aperture)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX)
+                    .prefix("aperture: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut camera.focus_distance)
+                    .speed(0.01)
+                    .clamp_range(0.001..=f32::MAX)
+                    .prefix("focus distance: "),
+            )
+            .on_hover_text("Click on the image to focus on what is under the mouse");
+        });
+
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut scene.settings.max_depth)
+                        .clamp_range(1..=64)
+                        .prefix("max bounces: "),
+                )
+                .changed();
+            let mut fixed_seed = scene.settings.seed.is_some();
+            if ui.checkbox(&mut fixed_seed, "fixed seed").changed() {
+                scene.settings.seed = fixed_seed.then_some(0);
+                changed = true;
+            }
+            if let Some(seed) = &mut scene.settings.seed {
+                changed |= ui.add(egui::DragValue::new(seed)).changed();
+            }
+            // raising the target keeps the samples already rendered, so it doesn't count as a change
+            ui.add(
+                egui::DragValue::new(&mut scene.settings.samples_per_pixel)
+                    .clamp_range(1..=u32::MAX)
+                    .prefix("samples target: "),
+            );
+        });
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, sphere) in scene.spheres.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Sphere {}", index));
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut sphere.center.x)
+                                .speed(0.01)
+                                .prefix("x: "),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut sphere.center.y)
+                                .speed(0.01)
+                                .prefix("y: "),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut sphere.center.z)
+                                .speed(0.01)
+                                .prefix("z: "),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut sphere.radius)
+                                .speed(0.01)
+                                .clamp_range(0.0..=f32::MAX)
+                                .prefix("r: "),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut sphere.material)
+                                .clamp_range(0..=last_material)
+                                .prefix("material: "),
+                        )
+                        .changed();
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+
+            for (index, mesh) in scene.meshes.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "Mesh {} \"{}\": {} triangles",
+                        index,
+                        mesh.name,
+                        mesh.triangles.len()
+                    ));
+                    if ui.button("Add instance").clicked() {
+                        added_instance = Some(index as u32);
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed_mesh = Some(index);
+                    }
+                });
+            }
+
+            for (index, instance) in scene.instances.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "Instance {} of \"{}\"",
+                        index, scene.meshes[instance.mesh as usize].name
+                    ));
+                    let mut material_override = instance.material.is_some();
+                    if ui.checkbox(&mut material_override, "material: ").changed() {
+                        instance.material = material_override.then_some(0);
+                        changed = true;
+                    }
+                    if let Some(material) = &mut instance.material {
+                        changed |= ui
+                            .add(egui::DragValue::new(material).clamp_range(0..=last_material))
+                            .changed();
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed_instance = Some(index);
+                    }
+                });
+                let transform = &mut instance.transform;
+                changed |= vector_ui(ui, "translation", &mut transform.translation, 0.01, None);
+                changed |= vector_ui(ui, "rotation", &mut transform.rotation, 1.0, None);
+                changed |= vector_ui(ui, "scale", &mut transform.scale, 0.01, Some(0.001));
+            }
+        });
+
+        if let Some(index) = removed {
+            scene.spheres.remove(index);
+            changed = true;
+        }
+        if let Some(index) = removed_mesh {
+            scene.remove_mesh(index);
+            changed = true;
+        }
+        if let Some(mesh) = added_instance {
+            scene.instances.push(Instance {
+                mesh,
+                transform: Transform::default(),
+                material: None,
+            });
+            changed = true;
+        }
+        if let Some(index) = removed_instance {
+            scene.instances.remove(index);
+            changed = true;
+        }
+        if ui.button("Add sphere").clicked() {
+            scene.spheres.push(Sphere {
+                center: Point3::new(0.0, 0.0, -2.0),
+                radius: 0.5,
+                material: 0,
+            });
+            changed = true;
+        }
+
+        if changed {
+            self.scene_changed();
+        }
+    }
+}
+
+/// the view behind the windows
+fn main_view_id() -> egui::Id {
+    egui::Id::new("main view")
+}
+
+/**
+ * Dragging with the left button orbits around the point the camera looks at,
+ * dragging with the right button looks around and scrolling zooms.
+ * While the mouse is over the image, WASD flies around with Q and E to go down and up
+ */
+fn camera_controls(ui: &egui::Ui, response: &egui::Response, camera: &mut Camera, scene: &Scene) {
+    if response.clicked() || response.drag_started() {
+        response.request_focus();
+    }
+
+    // clicking focuses on what is under the mouse
+    if let Some(position) = response
+        .interact_pointer_pos()
+        .filter(|_| response.clicked())
+    {
+        let rect = response.rect;
+        let image_position = (position - rect.min) / rect.size();
+        let ray = camera.ray_through(image_position, rect.width() / rect.height());
+        if let Some(distance) = scene.cast_ray(&ray) {
+            camera.focus_distance = distance;
+        }
+    }
+
+    let drag = response.drag_delta() * ROTATION_SPEED;
+    if response.dragged_by(egui::PointerButton::Primary) {
+        camera.orbit(-drag.x, drag.y);
+    } else if response.dragged_by(egui::PointerButton::Secondary) {
+        camera.look_around(-drag.x, drag.y);
+    }
+
+    if !response.hovered() {
+        return;
+    }
+    let scroll = ui.input(|input| input.scroll_delta.y);
+    if scroll != 0.0 {
+        camera.zoom((-scroll * ZOOM_SPEED).exp());
+    }
+
+    if ui.ctx().wants_keyboard_input() {
+        return; // typing in a text field
+    }
+    let movement = ui.input(|input| {
+        let axis = |negative, positive| {
+            input.key_down(positive) as i32 as f32 - input.key_down(negative) as i32 as f32
+        };
+        let speed = if input.modifiers.shift {
+            FAST_FLY_SPEED
+        } else {
+            FLY_SPEED
+        };
+        Vec3::new(
+            axis(egui::Key::A, egui::Key::D),
+            axis(egui::Key::Q, egui::Key::E),
+            axis(egui::Key::S, egui::Key::W),
+        ) * (speed * input.stable_dt)
+    });
+    if movement.length_squared() > 0.0 {
+        camera.fly(movement);
+        // keep moving while the key is held even if nothing else happens
+        ui.ctx().request_repaint();
+    }
+}
+
+/**
+ * The exposure and the tonemapper of a view
+ */
+fn display_ui(ui: &mut egui::Ui, display: &mut DisplaySettings) {
+    ui.add(
+        egui::Slider::new(&mut display.exposure, -10.0..=10.0)
+            .suffix(" EV")
+            .text("exposure"),
+    );
+    egui::ComboBox::new(ui.id().with("tonemapper"), "tonemapper")
+        .selected_text(display.tonemapper.name())
+        .show_ui(ui, |ui| {
+            for tonemapper in gpu::Tonemapper::ALL {
+                ui.selectable_value(&mut display.tonemapper, tonemapper, tonemapper.name());
+            }
+        });
+}
+
+/**
+ * The last render time with statistics and a plot of the history
+ */
+fn render_times_ui(ui: &mut egui::Ui, render_times: &gpu::TimeHistory) {
+    let (Some(last), Some(statistics)) = (render_times.last(), render_times.statistics()) else {
+        ui.label(format!(
+            "Render time ({}): not available",
+            render_times.source.label()
+        ));
+        return;
+    };
+    ui.label(format!(
+        "Render time ({}): {:.3} ms",
+        render_times.source.label(),
+        last
+    ));
+    ui.label(format!(
+        "min {:.3}, avg {:.3}, max {:.3}, p95 {:.3} ms",
+        statistics.min, statistics.average, statistics.max, statistics.p95
+    ));
+
+    let width = ui.available_width().max(200.0);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(width, 60.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    // the slowest frame is at the top
+    let y = |time: f64| rect.bottom() - (time / statistics.max.max(1e-9)) as f32 * rect.height();
+    let x_step = rect.width() / (gpu::HISTORY_LENGTH - 1) as f32;
+    let points = render_times
+        .times
+        .iter()
+        .enumerate()
+        .map(|(index, &time)| egui::pos2(rect.left() + index as f32 * x_step, y(time)))
+        .collect();
+    for (time, color) in [
+        (statistics.average, egui::Color32::DARK_GREEN),
+        (statistics.p95, egui::Color32::from_rgb(160, 100, 0)),
+    ] {
+        painter.hline(rect.x_range(), y(time), egui::Stroke::new(1.0, color));
+    }
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+}
+
+/**
+ * Why the shaders couldn't be reloaded, at the bottom of the window while the previous ones keep rendering
+ */
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+fn shader_errors_ui(context: &egui::Context, errors: &[gpu::ShaderError]) {
+    if errors.is_empty() {
+        return;
+    }
+    egui::TopBottomPanel::bottom("shader errors")
+        .resizable(true)
+        .show(context, |ui| {
+            ui.heading("Shader errors");
+            ui.label("The last shaders that worked are used until the errors are fixed");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (index, error) in errors.iter().enumerate() {
+                    let location = error.location.as_ref().map_or(String::new(), |location| {
+                        format!(
+                            "{}:{}:{}: ",
+                            location.module, location.line, location.column
+                        )
+                    });
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("{}{}", location, error.message),
+                    );
+                    if !error.details.is_empty() {
+                        egui::CollapsingHeader::new("details")
+                            .id_source(index)
+                            .show(ui, |ui| ui.monospace(&error.details));
+                    }
+                }
+            });
+        });
+}
+
+/**
+ * A row of three drag values, returns true when one of them changed
+ */
+fn vector_ui(
+    ui: &mut egui::Ui,
+    label: &str,
+    vector: &mut Vec3<f32>,
+    speed: f64,
+    minimum: Option<f32>,
+) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(format!("    {}", label));
+        for (axis, value) in [
+            ("x: ", &mut vector.x),
+            ("y: ", &mut vector.y),
+            ("z: ", &mut vector.z),
+        ] {
+            let drag = egui::DragValue::new(value).speed(speed).prefix(axis);
+            let drag = match minimum {
+                Some(minimum) => drag.clamp_range(minimum..=f32::MAX),
+                None => drag,
+            };
+            changed |= ui.add(drag).changed();
+        }
+    });
+    changed
 }
 
 impl eframe::App for AppUI {
     fn update(&mut self, context: &egui::Context, frame: &mut Frame) {
+        // before the central panel so it takes the space it needs
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        shader_errors_ui(context, self.ray_tracer.shader_errors());
         egui::CentralPanel::default()
             .frame(egui::Frame::none())
             .show(context, |ui| {
@@ -50,19 +614,85 @@ impl eframe::App for AppUI {
                 egui::Window::new("Info")
                     .default_size((100.0, 100.0))
                     .show(context, |ui| {
-                        ui.label(format!("Here is the size: {:?}", size));
-                        let shader_time = f64::from_bits(self.render_time.load(Ordering::Relaxed));
-                        let shader_time = if shader_time.is_nan() {
-                            "Shader run time: not available".to_string()
-                        } else {
-                            format!("Shader run time: {:.3} ms", shader_time)
-                        };
-                        ui.label(shader_time);
+                        let pixels = size * context.pixels_per_point();
+                        ui.label(format!(
+                            "Here is the size: {:?}, {}x{} physical pixels",
+                            size, pixels.x, pixels.y
+                        ));
+                        ui.add(
+                            egui::Slider::new(&mut self.render_scale, 25..=200)
+                                .suffix("%")
+                                .text("render scale"),
+                        );
+                        let [width, height] = self.ray_tracer.workgroup_size;
+                        egui::ComboBox::from_label("workgroup size")
+                            .selected_text(format!("{}x{}", width, height))
+                            .show_ui(ui, |ui| {
+                                for size in gpu::WORKGROUP_SIZES {
+                                    let text = format!("{}x{}", size[0], size[1]);
+                                    ui.selectable_value(
+                                        &mut self.ray_tracer.workgroup_size,
+                                        size,
+                                        text,
+                                    );
+                                }
+                            });
+                        ui.horizontal(|ui| display_ui(ui, &mut self.display));
+                        if let Some(render_times) = self.ray_tracer.render_times(main_view_id()) {
+                            render_times_ui(ui, &render_times);
+                        }
+
+                        ui.label(format!(
+                            "Samples: {} / {}",
+                            self.ray_tracer.sample_count(main_view_id()),
+                            self.scene.settings.samples_per_pixel
+                        ));
 
                         if let Some(usage) = frame.info().cpu_usage {
                             ui.label(format!("egui render time: {:.3} ms", usage * 1000.0));
                         }
+
+                        let bvh = &self.scene.bvh;
+                        ui.label(format!(
+                            "Top level BVH: {} nodes built in {:.3} ms",
+                            bvh.nodes.len(),
+                            bvh.build_time.as_secs_f64() * 1000.0
+                        ));
+                        let mesh_nodes: usize = self
+                            .scene
+                            .meshes
+                            .iter()
+                            .map(|mesh| mesh.bvh.nodes.len())
+                            .sum();
+                        ui.label(format!(
+                            "Mesh BVHs: {} nodes in {} meshes",
+                            mesh_nodes,
+                            self.scene.meshes.len()
+                        ));
+
+                        ui.horizontal(|ui| {
+                            ui.label("New view:");
+                            let camera = self.scene.camera;
+                            if ui.button("Top").clicked() {
+                                self.add_view("Top", camera.at_angle_from_up(0.0));
+                            }
+                            if ui.button("Front").clicked() {
+                                self.add_view("Front", camera.at_angle_from_up(PI / 2.0));
+                            }
+                            if ui.button("Copy of the camera").clicked() {
+                                self.add_view("View", camera);
+                            }
+                        });
+
+                        #[cfg(not(target_arch = "wasm32"))]
+                        self.save_render_ui(ui);
                     });
+
+                self.extra_views_ui(context);
+
+                egui::Window::new("Scene")
+                    .default_open(false)
+                    .show(context, |ui| self.scene_ui(ui));
             });
     }
 }
@@ -0,0 +1,218 @@
+use std::path::PathBuf;
+
+use web_time::Instant;
+
+use rt_shader::export::{self, ImageFormat};
+use rt_shader::gpu;
+use rt_shader::ray_tracer::scene::Scene;
+use rt_shader::ray_tracer::scene_file::load_scene;
+use rt_shader::Renderer;
+
+pub const USAGE: &str = "usage: rt_shader render [scene file] [--size WIDTHxHEIGHT] [--samples N] [--exposure EV] [--tonemapper clamp|reinhard|aces|agx] [--output FILE.png|FILE.exr|FILE.pfm]... [--software]";
+
+/**
+ * What to render without a window, from the arguments of the render subcommand
+ */
+struct HeadlessOptions {
+    /// the example scene is rendered without one
+    scene_path: Option<PathBuf>,
+    size: [u32; 2],
+    /// the samples target of the scene when not given
+    samples: Option<u32>,
+    display: gpu::DisplaySettings,
+    /// the image is saved once in each of these, in the format of their extension
+    outputs: Vec<PathBuf>,
+    /// only use the software adapter, otherwise it's only used when there is no gpu
+    software: bool,
+}
+
+impl HeadlessOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = HeadlessOptions {
+            scene_path: None,
+            size: [800, 600],
+            samples: None,
+            display: gpu::DisplaySettings::default(),
+            outputs: Vec::new(),
+            software: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--size" => options.size = parse_size(option_value(&mut args, arg)?)?,
+                "--samples" => {
+                    let samples = option_value(&mut args, arg)?;
+                    options.samples = match samples.parse() {
+                        Ok(samples) if samples > 0 => Some(samples),
+                        _ => return Err(format!("invalid number of samples: {}", samples)),
+                    };
+                }
+                "--exposure" => {
+                    let exposure = option_value(&mut args, arg)?;
+                    options.display.exposure = exposure
+                        .parse()
+                        .map_err(|_| format!("invalid exposure: {}", exposure))?;
+                }
+                "--tonemapper" => {
+                    let name = option_value(&mut args, arg)?;
+                    options.display.tonemapper = gpu::Tonemapper::ALL
+                        .into_iter()
+                        .find(|tonemapper| tonemapper.name() == name)
+                        .ok_or_else(|| format!("unknown tonemapper {}\n{}", name, USAGE))?;
+                }
+                "--output" => {
+                    let output = PathBuf::from(option_value(&mut args, arg)?);
+                    if ImageFormat::from_path(&output).is_none() {
+                        return Err(format!("{}: {}", output.display(), export::UNKNOWN_FORMAT));
+                    }
+                    options.outputs.push(output);
+                }
+                "--software" => options.software = true,
+                "--help" => return Err(USAGE.to_string()),
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option {}\n{}", arg, USAGE))
+                }
+                _ if options.scene_path.is_none() => options.scene_path = Some(PathBuf::from(arg)),
+                _ => return Err(format!("only one scene can be rendered\n{}", USAGE)),
+            }
+        }
+        if options.outputs.is_empty() {
+            options.outputs.push(PathBuf::from("render.png"));
+        }
+        Ok(options)
+    }
+}
+
+fn option_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<&'a String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} needs a value\n{}", option, USAGE))
+}
+
+/**
+ * A size written like 1920x1080
+ */
+fn parse_size(size: &str) -> Result<[u32; 2], String> {
+    let invalid = || format!("invalid size {}, expected WIDTHxHEIGHT", size);
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok([width, height]),
+        _ => Err(invalid()),
+    }
+}
+
+/**
+ * Renders a scene to an image file without opening a window, with the arguments after the render subcommand
+ */
+pub fn render(args: &[String]) -> Result<(), String> {
+    let options = HeadlessOptions::parse(args)?;
+    let scene = match &options.scene_path {
+        Some(path) => load_scene(path).map_err(|error| error.to_string())?,
+        None => Scene::example(),
+    };
+    let samples = options
+        .samples
+        .unwrap_or(scene.settings.samples_per_pixel)
+        .max(1);
+    let mut renderer = Renderer::new(options.software)?;
+    let info = renderer.adapter_info();
+    eprintln!("rendering on {} ({:?})", info.name, info.backend);
+
+    renderer.set_scene(&scene, options.size)?;
+    renderer.set_display(&options.display);
+    let start = Instant::now();
+    renderer.render(samples);
+    eprintln!(
+        "rendered {} samples in {:.2} s",
+        samples,
+        start.elapsed().as_secs_f64()
+    );
+
+    for output in &options.outputs {
+        renderer.save(output)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rt_shader::export;
+    use rt_shader::gpu::Tonemapper;
+
+    use super::{parse_size, HeadlessOptions};
+
+    fn parse(args: &str) -> Result<HeadlessOptions, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        HeadlessOptions::parse(&args)
+    }
+
+    #[test]
+    fn sizes_are_a_width_and_a_height() {
+        assert_eq!(parse_size("1920x1080"), Ok([1920, 1080]));
+        for size in [
+            "0x10", "10x0", "10x", "x10", "10", "10x10x10", "-1x10", "ax10",
+        ] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+
+    #[test]
+    fn options_are_read_in_any_order() {
+        let options = parse(
+            "--samples 16 scene.ron --tonemapper aces --size 64x48 --exposure -1.5 --software",
+        )
+        .unwrap();
+        assert_eq!(options.scene_path, Some(PathBuf::from("scene.ron")));
+        assert_eq!(options.size, [64, 48]);
+        assert_eq!(options.samples, Some(16));
+        assert_eq!(options.display.tonemapper, Tonemapper::Aces);
+        assert_eq!(options.display.exposure, -1.5);
+        assert!(options.software);
+        assert_eq!(options.outputs, [PathBuf::from("render.png")]);
+    }
+
+    #[test]
+    fn every_output_is_kept() {
+        let options = parse("--output a.png --output b.exr --output c.pfm").unwrap();
+        assert_eq!(
+            options.outputs,
+            ["a.png", "b.exr", "c.pfm"].map(PathBuf::from)
+        );
+        let error = parse("--output a.png --output b.jpg").err().unwrap();
+        assert_eq!(error, format!("b.jpg: {}", export::UNKNOWN_FORMAT));
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        for option in [
+            "--size",
+            "--samples",
+            "--exposure",
+            "--tonemapper",
+            "--output",
+        ] {
+            let error = parse(option).err().unwrap();
+            assert!(
+                error.starts_with(&format!("{} needs a value", option)),
+                "{}",
+                error
+            );
+        }
+        let error = parse("--sample 16").err().unwrap();
+        assert!(error.starts_with("unknown option --sample"), "{}", error);
+        for args in [
+            "--samples 0",
+            "--samples many",
+            "--size 10x",
+            "--tonemapper filmic",
+            "--exposure bright",
+            "a.ron b.ron",
+        ] {
+            assert!(parse(args).is_err(), "{}", args);
+        }
+    }
+}
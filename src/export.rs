@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use eframe::egui_wgpu::wgpu::{Device, Queue};
+
+use crate::gpu;
+
+/**
+ * The formats a render can be saved in, chosen from the extension of the file
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// the image as it's shown, tonemapped in srgb with 8 bits per channel
+    Png,
+    /// OpenEXR, the radiance in 32 bit floats in the R, G and B channels
+    Exr,
+    /// portable float map, the radiance in 32 bit floats
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "exr" => Some(ImageFormat::Exr),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+pub const UNKNOWN_FORMAT: &str = "unknown image format, expected a .png, .exr or .pfm file";
+
+/**
+ * Copies the image back from the gpu and saves it in the format of the extension of the path
+ */
+pub fn save_render(
+    path: &Path,
+    device: &Device,
+    queue: &Queue,
+    shared: &gpu::SharedResources,
+    viewport: &gpu::ViewportResources,
+) -> Result<(), String> {
+    let format = ImageFormat::from_path(path).ok_or(UNKNOWN_FORMAT)?;
+    if viewport.sample_count == 0 {
+        return Err("nothing was rendered yet".to_string());
+    }
+    let size = viewport.accumulated_size;
+    let result = match format {
+        ImageFormat::Png => save_png(
+            path,
+            size,
+            &gpu::read_display(device, queue, shared, viewport),
+        ),
+        ImageFormat::Exr | ImageFormat::Pfm => {
+            let radiance = gpu::read_radiance(device, queue, viewport)
+                .expect("there is a sample in the image");
+            if format == ImageFormat::Exr {
+                save_exr(path, size, &radiance)
+            } else {
+                save_pfm(path, size, &radiance)
+            }
+        }
+    };
+    result.map_err(|error| format!("couldn't write {}: {}", path.display(), error))
+}
+
+/**
+ * Saves rgba bytes, row after row from the top
+ */
+fn save_png(path: &Path, size: [u32; 2], pixels: &[u8]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/**
+ * Saves rgba floats, row after row from the top, as an uncompressed scanline OpenEXR file
+ * with R, G and B channels in 32 bit floats. The alpha is always 1 so it's left out
+ */
+fn save_exr(path: &Path, size: [u32; 2], pixels: &[f32]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_exr(&mut file, size, pixels)?;
+    file.flush()
+}
+
+fn write_exr(file: &mut impl Write, size: [u32; 2], pixels: &[f32]) -> io::Result<()> {
+    let header = exr_header(size);
+    file.write_all(&header)?;
+
+    let [width, height] = size.map(|size| size as usize);
+    // the blocks of one scanline follow the table of their offsets, from the start of the file
+    let header_size = header.len();
+    let block_size = 2 * 4 + width * EXR_CHANNELS.len() * 4;
+    for y in 0..height {
+        let offset = header_size + height * 8 + y * block_size;
+        file.write_all(&(offset as u64).to_le_bytes())?;
+    }
+    for (y, row) in pixels.chunks(width * 4).enumerate() {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&((block_size - 2 * 4) as i32).to_le_bytes())?;
+        // the channels of a scanline come one after the other
+        for &(_, channel) in &EXR_CHANNELS {
+            for pixel in row.chunks(4) {
+                file.write_all(&pixel[channel].to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the names of the channels with their index in a rgba pixel, sorted by name like the format wants
+const EXR_CHANNELS: [(&str, usize); 3] = [("B", 2), ("G", 1), ("R", 0)];
+
+fn exr_header(size: [u32; 2]) -> Vec<u8> {
+    fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        for text in [name, kind] {
+            header.extend_from_slice(text.as_bytes());
+            header.push(0);
+        }
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+    let int = |value: i32| value.to_le_bytes();
+    let float = |value: f32| value.to_le_bytes();
+
+    // the magic number and version 2, a single part scanline file
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+    let mut channels = Vec::new();
+    for (name, _) in EXR_CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&int(2)); // 32 bit float
+        channels.extend_from_slice(&[0; 4]); // not perceptually linear and reserved
+        channels.extend_from_slice(&int(1)); // x and y sampling
+        channels.extend_from_slice(&int(1));
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[0]); // none
+    let window: Vec<u8> = [0, 0, size[0] as i32 - 1, size[1] as i32 - 1]
+        .into_iter()
+        .flat_map(int)
+        .collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]); // increasing y
+    attribute(&mut header, "pixelAspectRatio", "float", &float(1.0));
+    attribute(
+        &mut header,
+        "screenWindowCenter",
+        "v2f",
+        &[float(0.0), float(0.0)].concat(),
+    );
+    attribute(&mut header, "screenWindowWidth", "float", &float(1.0));
+    header.push(0);
+    header
+}
+
+/**
+ * Saves rgba floats, row after row from the top, as a color portable float map
+ */
+fn save_pfm(path: &Path, size: [u32; 2], pixels: &[f32]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_pfm(&mut file, size, pixels)?;
+    file.flush()
+}
+
+fn write_pfm(file: &mut impl Write, size: [u32; 2], pixels: &[f32]) -> io::Result<()> {
+    // a negative scale means little endian
+    write!(file, "PF\n{} {}\n-1.0\n", size[0], size[1])?;
+    // the rows go from the bottom to the top
+    for row in pixels.chunks(size[0] as usize * 4).rev() {
+        for pixel in row.chunks(4) {
+            for channel in &pixel[..3] {
+                file.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_exr, write_pfm};
+
+    /// a 2x2 image where every channel of every pixel has its own value
+    fn pixels() -> Vec<f32> {
+        (0..4)
+            .flat_map(|pixel| {
+                let value = pixel as f32;
+                [value + 0.1, value + 0.2, value + 0.3, 1.0]
+            })
+            .collect()
+    }
+
+    fn int(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks(4)
+            .map(|float| f32::from_le_bytes(float.try_into().unwrap()))
+            .collect()
+    }
+
+    /// the name, type and value of the attributes of the header, and where the header ends
+    fn exr_attributes(bytes: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let mut attributes = Vec::new();
+        let mut at = 8;
+        let text = |at: &mut usize| {
+            let end = *at + bytes[*at..].iter().position(|&byte| byte == 0).unwrap();
+            let text = String::from_utf8(bytes[*at..end].to_vec()).unwrap();
+            *at = end + 1;
+            text
+        };
+        loop {
+            let name = text(&mut at);
+            if name.is_empty() {
+                return (attributes, at);
+            }
+            let kind = text(&mut at);
+            let size = int(bytes, at) as usize;
+            attributes.push((name, kind, bytes[at + 4..at + 4 + size].to_vec()));
+            at += 4 + size;
+        }
+    }
+
+    #[test]
+    fn exr_files_have_a_header_an_offset_table_and_a_block_per_row() {
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, [2, 2], &pixels()).unwrap();
+
+        // the magic number, then version 2 without any flag
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(bytes[4..8], [2, 0, 0, 0]);
+
+        let (attributes, header_size) = exr_attributes(&bytes);
+        let (_, kind, channels) = &attributes[0];
+        assert_eq!(kind, "chlist");
+        let mut expected = Vec::new();
+        for name in ["B", "G", "R"] {
+            expected.extend_from_slice(name.as_bytes());
+            // 32 bit floats, not perceptually linear, sampled on every pixel
+            expected.extend_from_slice(&[0, 2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        expected.push(0);
+        assert_eq!(channels, &expected);
+        let (_, _, data_window) = attributes
+            .iter()
+            .find(|(name, _, _)| name == "dataWindow")
+            .unwrap();
+        assert_eq!(
+            (0..4)
+                .map(|index| int(data_window, index * 4))
+                .collect::<Vec<_>>(),
+            [0, 0, 1, 1]
+        );
+
+        // a row is its y and its size, then the 2 pixels of every channel
+        let block_size = 4 + 4 + 3 * 2 * 4;
+        assert_eq!(bytes.len(), header_size + 2 * 8 + 2 * block_size);
+        for y in 0..2 {
+            let offset = u64::from_le_bytes(bytes[header_size + y * 8..][..8].try_into().unwrap());
+            let offset = offset as usize;
+            assert_eq!(offset, header_size + 2 * 8 + y * block_size);
+            assert_eq!(int(&bytes, offset), y as i32);
+            assert_eq!(int(&bytes, offset + 4), block_size as i32 - 8);
+            let first = 2.0 * y as f32;
+            assert_eq!(
+                floats(&bytes[offset + 8..offset + block_size]),
+                [
+                    first + 0.3,
+                    first + 1.3,
+                    first + 0.2,
+                    first + 1.2,
+                    first + 0.1,
+                    first + 1.1
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn pfm_files_go_from_the_bottom_row_to_the_top() {
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, [2, 2], &pixels()).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        assert_eq!(
+            floats(&bytes[header.len()..]),
+            [
+                2.1, 2.2, 2.3, 3.1, 3.2, 3.3, // the bottom row
+                0.1, 0.2, 0.3, 1.1, 1.2, 1.3,
+            ]
+        );
+    }
+}
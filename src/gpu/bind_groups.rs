@@ -0,0 +1,218 @@
+use std::num::NonZeroU64;
+
+use eframe::egui_wgpu::wgpu::*;
+
+use super::wgsl::{assert_layout, AddressSpace, WgslType};
+
+/**
+ * Declares the resources of a bind group once, in the order of their bindings in the shader.
+ * The layout is built from it and the bind groups are checked against it
+ */
+pub struct BindGroupBuilder {
+    label: &'static str,
+    entries: Vec<BindGroupLayoutEntry>,
+}
+
+impl BindGroupBuilder {
+    pub fn new(label: &'static str) -> Self {
+        BindGroupBuilder {
+            label,
+            entries: Vec::new(),
+        }
+    }
+
+    /**
+     * A uniform buffer holding a T, the buffer has to be at least as big as it.
+     * T has to be laid out like in wgsl or this doesn't compile
+     */
+    pub fn uniform<T: WgslType>(self, visibility: ShaderStages) -> Self {
+        const { assert_layout::<T>(AddressSpace::Uniform) };
+        self.entry(
+            visibility,
+            BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+            },
+        )
+    }
+
+    /**
+     * A read only storage buffer of T, the buffer has to hold at least one of them.
+     * T has to be laid out like in wgsl or this doesn't compile
+     */
+    pub fn storage<T: WgslType>(self, visibility: ShaderStages) -> Self {
+        const { assert_layout::<T>(AddressSpace::Storage) };
+        self.entry(
+            visibility,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+            },
+        )
+    }
+
+    /**
+     * A 2d texture of floats that is read, with a sampler only if it's filterable
+     */
+    pub fn texture(self, visibility: ShaderStages, filterable: bool) -> Self {
+        self.entry(
+            visibility,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+        )
+    }
+
+    /**
+     * A 2d texture that is written
+     */
+    pub fn storage_texture(self, visibility: ShaderStages, format: TextureFormat) -> Self {
+        self.entry(
+            visibility,
+            BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: TextureViewDimension::D2,
+            },
+        )
+    }
+
+    pub fn sampler(self, visibility: ShaderStages) -> Self {
+        self.entry(
+            visibility,
+            BindingType::Sampler(SamplerBindingType::Filtering),
+        )
+    }
+
+    fn entry(mut self, visibility: ShaderStages, ty: BindingType) -> Self {
+        self.entries.push(BindGroupLayoutEntry {
+            binding: self.entries.len() as u32,
+            visibility,
+            ty,
+            count: None,
+        });
+        self
+    }
+
+    pub fn build(self, device: &Device) -> BindGroupSchema {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(self.label),
+            entries: &self.entries,
+        });
+        BindGroupSchema {
+            layout,
+            label: self.label,
+            entries: self.entries,
+        }
+    }
+}
+
+/**
+ * The layout of a bind group with what was declared to build it
+ */
+pub struct BindGroupSchema {
+    pub layout: BindGroupLayout,
+    label: &'static str,
+    entries: Vec<BindGroupLayoutEntry>,
+}
+
+impl BindGroupSchema {
+    /**
+     * A bind group with the resources in the order they were declared,
+     * panics when they don't match the declaration
+     */
+    pub fn create_bind_group(&self, device: &Device, resources: &[BindingResource]) -> BindGroup {
+        assert_eq!(
+            resources.len(),
+            self.entries.len(),
+            "{} declares {} resources",
+            self.label,
+            self.entries.len()
+        );
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .zip(resources)
+            .map(|(entry, resource)| {
+                if let Err(error) = check_resource(&entry.ty, resource) {
+                    panic!("binding {} of {}: {}", entry.binding, self.label, error);
+                }
+                BindGroupEntry {
+                    binding: entry.binding,
+                    resource: resource.clone(),
+                }
+            })
+            .collect();
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some(self.label),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+}
+
+/**
+ * Whether a resource can be bound where a binding was declared,
+ * buffers need the usage of their binding and to be as big as what they hold
+ */
+fn check_resource(ty: &BindingType, resource: &BindingResource) -> Result<(), &'static str> {
+    match (ty, resource) {
+        (
+            BindingType::Buffer {
+                ty,
+                min_binding_size,
+                ..
+            },
+            BindingResource::Buffer(binding),
+        ) => {
+            let usage = match ty {
+                BufferBindingType::Uniform => BufferUsages::UNIFORM,
+                BufferBindingType::Storage { .. } => BufferUsages::STORAGE,
+            };
+            if !binding.buffer.usage().contains(usage) {
+                return Err("the buffer wasn't created with the usage of the binding");
+            }
+            let size = binding
+                .size
+                .map_or(binding.buffer.size() - binding.offset, BufferSize::get);
+            if min_binding_size.is_some_and(|min_size| size < min_size.get()) {
+                return Err("the buffer is smaller than what was declared");
+            }
+            Ok(())
+        }
+        (BindingType::Texture { .. }, BindingResource::TextureView(_))
+        | (BindingType::StorageTexture { .. }, BindingResource::TextureView(_))
+        | (BindingType::Sampler(_), BindingResource::Sampler(_)) => Ok(()),
+        _ => Err("it isn't the kind of resource that was declared"),
+    }
+}
+
+/**
+ * The passes that take bind groups, so both kinds number them the same way
+ */
+pub trait SetBindGroups<'a> {
+    /**
+     * Sets the bind groups at the indices of their layouts in the pipeline, in the same order
+     */
+    fn set_bind_groups(&mut self, bind_groups: &[&'a BindGroup]);
+}
+
+impl<'a> SetBindGroups<'a> for RenderPass<'a> {
+    fn set_bind_groups(&mut self, bind_groups: &[&'a BindGroup]) {
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            self.set_bind_group(index as u32, bind_group, &[]);
+        }
+    }
+}
+
+impl<'a> SetBindGroups<'a> for ComputePass<'a> {
+    fn set_bind_groups(&mut self, bind_groups: &[&'a BindGroup]) {
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            self.set_bind_group(index as u32, bind_group, &[]);
+        }
+    }
+}
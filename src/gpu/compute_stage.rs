@@ -1,49 +1,136 @@
 use eframe::wgpu::*;
 
-pub type ComputeBindGroups = Vec<BindGroup>;
-pub type ComputeBindGroupLayout = Vec<BindGroupLayout>;
+use super::bind_groups::{BindGroupBuilder, BindGroupSchema};
+use super::shader_modules::{ComposedShader, ShaderError, ShaderModules};
+use super::shared_stage_data::{
+    GpuBvhNode, GpuCamera, GpuInstance, GpuMaterial, GpuSphere, GpuTriangle, GpuVertex,
+    SharedStageUniform,
+};
+use super::wgsl::{with_declarations, WgslStruct};
+
+/// two of them, the accumulation textures swap places every frame
+pub type ComputeBindGroups = [BindGroup; 2];
+
+const ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
 
 /**
- * it returns the bindgroup layout, the texture view needed for the fragment pass
- * and an array containing the indices of all the shared bind group entries
+ * The output and accumulation textures of a viewport
  */
-pub fn get_compute_bind_group(
+pub fn get_compute_bind_group_schema(
     device: &Device,
-    view: &TextureView,
     texture_format: TextureFormat,
-) -> (ComputeBindGroupLayout, ComputeBindGroups) {
+) -> BindGroupSchema {
     // the bindgroup will only be used for the compute shader part
-    let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("Layout for the compute bind group"),
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::COMPUTE,
-            ty: BindingType::StorageTexture {
-                access: StorageTextureAccess::WriteOnly,
-                format: texture_format,
-                view_dimension: TextureViewDimension::D2,
+    BindGroupBuilder::new("Compute bind group")
+        .storage_texture(ShaderStages::COMPUTE, texture_format)
+        // the sum of the samples of the previous frames
+        .texture(ShaderStages::COMPUTE, false)
+        // the sum with the samples of this frame
+        .storage_texture(ShaderStages::COMPUTE, ACCUMULATION_FORMAT)
+        .build(device)
+}
+
+/**
+ * Two bind groups that only differ by which accumulation texture is read and which one is written,
+ * they are used one frame out of two. They are created again when the textures are resized
+ */
+pub fn create_compute_bind_groups(
+    device: &Device,
+    schema: &BindGroupSchema,
+    view: &TextureView,
+    accumulation_views: &[TextureView; 2],
+) -> ComputeBindGroups {
+    // a rgba32float texture can't be read and written in the same pass, so they take turns
+    [(0, 1), (1, 0)].map(|(read, write)| {
+        schema.create_bind_group(
+            device,
+            &[
+                // when you send a texture to the gpu you only send the view to the texture
+                BindingResource::TextureView(view),
+                BindingResource::TextureView(&accumulation_views[read]),
+                BindingResource::TextureView(&accumulation_views[write]),
+            ],
+        )
+    })
+}
+
+/**
+ * The two textures where the samples of every frame are added up
+ */
+pub fn get_accumulation_textures(
+    device: &Device,
+    size: [u32; 2],
+) -> ([Texture; 2], [TextureView; 2]) {
+    let textures = [0, 1].map(|_| {
+        device.create_texture(&TextureDescriptor {
+            dimension: TextureDimension::D2,
+            format: ACCUMULATION_FORMAT,
+            label: Some("Ray tracer accumulation texture"),
+            mip_level_count: 1,
+            sample_count: 1,
+            // copied back to save the image in high dynamic range
+            usage: TextureUsages::COPY_SRC
+                | TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING,
+            size: Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
             },
-            count: None,
-        }],
+            view_formats: &[],
+        })
     });
+    let views = textures
+        .each_ref()
+        .map(|texture| texture.create_view(&TextureViewDescriptor::default()));
+    (textures, views)
+}
 
-    let bind_group = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("Bind group for the compute bind group"),
-        layout: &bind_group_layout,
-        entries: &[BindGroupEntry {
-            binding: 0,
-            resource: BindingResource::TextureView(view), // when you send a texture to the gpu you only send the view to the texture
-        }],
-    });
+/// the width and height of the workgroups the ui can choose from, none of them
+/// go over the 256 invocations every device supports
+pub const WORKGROUP_SIZES: [[u32; 2]; 5] = [[8, 8], [16, 16], [32, 8], [8, 32], [64, 4]];
+pub const DEFAULT_WORKGROUP_SIZE: [u32; 2] = [16, 16];
 
-    (vec![bind_group_layout], vec![bind_group])
+/**
+ * The code of the compute shader with the structs of the buffers, for workgroups of this size
+ */
+pub fn compute_shader_source(
+    modules: &ShaderModules,
+    workgroup_size: [u32; 2],
+) -> Result<ComposedShader, ShaderError> {
+    // wgpu doesn't support override constants yet so the size is defined in the code
+    let mut shader = modules.shader_source(
+        "raytracing.wgsl",
+        &[
+            ("WORKGROUP_WIDTH", workgroup_size[0].to_string()),
+            ("WORKGROUP_HEIGHT", workgroup_size[1].to_string()),
+        ],
+    )?;
+    shader.code = with_declarations(
+        &shader.code,
+        &[
+            SharedStageUniform::declaration(),
+            GpuCamera::declaration(),
+            GpuSphere::declaration(),
+            GpuMaterial::declaration(),
+            GpuVertex::declaration(),
+            GpuTriangle::declaration(),
+            GpuInstance::declaration(),
+            GpuBvhNode::declaration(),
+        ],
+    );
+    Ok(shader)
 }
 
 pub fn get_compute_pipeline(
     device: &Device,
+    shader: &ComposedShader,
     bind_group_layouts: &[&BindGroupLayout],
 ) -> ComputePipeline {
-    let shader = device.create_shader_module(include_wgsl!("../shaders/raytracing.wgsl"));
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("raytracing.wgsl"),
+        source: ShaderSource::Wgsl(shader.code.as_str().into()),
+    });
 
     let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: Some("Compute Pipeline layout"),
@@ -58,3 +145,43 @@ pub fn get_compute_pipeline(
         module: &shader,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gpu::shader_modules::ShaderModules;
+    use crate::gpu::shared_stage_data::{
+        DIELECTRIC, EMISSIVE, LAMBERTIAN, METAL, NO_MATERIAL, NO_NODE,
+    };
+    use crate::ray_tracer::bvh::MAX_BVH_DEPTH;
+
+    use super::{compute_shader_source, DEFAULT_WORKGROUP_SIZE};
+
+    /// the value of a `const NAME = value;` of the shader with its modules
+    fn shader_constant(name: &str) -> String {
+        let modules = ShaderModules::embedded();
+        let shader = compute_shader_source(&modules, DEFAULT_WORKGROUP_SIZE).unwrap();
+        let declaration = format!("const {} = ", name);
+        let start = shader.code.find(&declaration).unwrap() + declaration.len();
+        let length = shader.code[start..].find(';').unwrap();
+        shader.code[start..start + length].to_string()
+    }
+
+    #[test]
+    fn the_constants_of_the_shader_are_the_ones_of_the_cpu() {
+        // the shader would drop the nodes deeper than its stack
+        assert_eq!(shader_constant("MAX_BVH_DEPTH"), MAX_BVH_DEPTH.to_string());
+        assert_eq!(shader_constant("NO_NODE"), format!("{:#x}u", NO_NODE));
+        assert_eq!(
+            shader_constant("NO_MATERIAL"),
+            format!("{:#x}u", NO_MATERIAL)
+        );
+        for (name, kind) in [
+            ("LAMBERTIAN", LAMBERTIAN),
+            ("METAL", METAL),
+            ("DIELECTRIC", DIELECTRIC),
+            ("EMISSIVE", EMISSIVE),
+        ] {
+            assert_eq!(shader_constant(name), format!("{}u", kind), "{}", name);
+        }
+    }
+}
@@ -1,93 +1,87 @@
+mod bind_groups;
 mod compute_stage;
+#[cfg(not(target_arch = "wasm32"))]
+mod readback;
 mod render_stage;
 mod renderer;
+mod shader_modules;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+mod shader_reload;
 mod shared_stage_data;
-
-pub use renderer::RenderCallBack;
+mod time_query;
+mod viewport;
+mod wgsl;
+
+pub use compute_stage::{DEFAULT_WORKGROUP_SIZE, WORKGROUP_SIZES};
+#[cfg(not(target_arch = "wasm32"))]
+pub use readback::{read_display, read_radiance};
+pub use render_stage::{DisplaySettings, Tonemapper};
+pub use renderer::{
+    new_scene_revision, RenderCallBack, RenderResources, SharedResources, WidgetResources,
+};
+pub use shader_modules::{ShaderError, ShaderLocation};
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+pub use shader_reload::{ShaderWatcher, POLL_INTERVAL};
+pub use time_query::{TimeHistory, HISTORY_LENGTH};
+pub use viewport::ViewportResources;
+
+use std::collections::HashMap;
 
 use eframe::egui_wgpu::wgpu::*;
-use eframe::wgpu::util::DeviceExt;
-
-use renderer::RenderResources;
-
-use self::compute_stage::{get_compute_bind_group, get_compute_pipeline};
-use self::render_stage::{get_output_texture, get_render_bind_group, get_render_pipeline};
-use self::shared_stage_data::{get_shared_data, get_shared_stage_bind_group};
 
-pub fn get_render_resources(wgpu_render_state: &eframe::egui_wgpu::RenderState) -> RenderResources {
-    let device = &wgpu_render_state.device;
+use self::compute_stage::{
+    compute_shader_source, get_compute_bind_group_schema, get_compute_pipeline,
+};
+use self::render_stage::{
+    get_render_bind_group_schema, get_render_pipeline, get_texture_sampler, render_shader_source,
+    OUTPUT_FORMAT,
+};
+use self::renderer::{BindGroupLayouts, PipelineBindings};
+use self::shader_modules::ShaderModules;
+use self::shared_stage_data::{get_shared_data, get_shared_stage_bind_group_schema};
 
+/**
+ * Creates the pipelines and everything they use, for the app and for rendering without a window.
+ * The target format is the format of the texture the image is painted on.
+ * There is no viewport yet, each one is created the first time it's painted
+ */
+pub fn get_render_resources(device: &Device, target_format: TextureFormat) -> RenderResources {
     let shared_stage_data = get_shared_data(device);
-
-    let (shared_bind_group_layouts, shared_stage_bind_groups) =
-        get_shared_stage_bind_group(device, &shared_stage_data);
-
-    let (texture, texture_view) = get_output_texture(device);
-
-    let (compute_bind_group_layouts, compute_bind_groups) =
-        get_compute_bind_group(device, &texture_view, texture.format());
-
-    let (render_bind_group_layouts, render_bind_groups) =
-        get_render_bind_group(device, &texture_view);
+    let layouts = BindGroupLayouts {
+        compute: get_compute_bind_group_schema(device, OUTPUT_FORMAT),
+        render: get_render_bind_group_schema(device),
+        shared_stage: get_shared_stage_bind_group_schema(device),
+    };
+
+    let texture_sampler = get_texture_sampler(device);
+    // the modules built in the binary are put together in the tests
+    let shader_modules = ShaderModules::embedded();
+    let render_shader = render_shader_source(&shader_modules, target_format)
+        .expect("the render shader is put together");
     let render_pipeline = get_render_pipeline(
         device,
-        wgpu_render_state,
-        &concat_bind_group_layouts(&render_bind_group_layouts, &shared_bind_group_layouts),
+        &render_shader,
+        target_format,
+        &PipelineBindings::RENDER.layouts(&layouts),
     );
+    let compute_shader = compute_shader_source(&shader_modules, DEFAULT_WORKGROUP_SIZE)
+        .expect("the compute shader is put together");
     let compute_pipeline = get_compute_pipeline(
         device,
-        &concat_bind_group_layouts(&compute_bind_group_layouts, &shared_bind_group_layouts),
+        &compute_shader,
+        &PipelineBindings::COMPUTE.layouts(&layouts),
     );
-    let adapter = &wgpu_render_state.adapter;
     RenderResources {
-        render_pipeline,
-        render_bind_groups,
-        compute_bind_groups,
-        compute_pipeline,
-        shared_stage_data,
-        shared_stage_bind_groups,
-        time_query: get_time_query(device, &adapter),
-    }
-}
-
-/**
- * The query for a timestamp to see how long the compute shader lasts
- */
-fn get_time_query(device: &Device, adapter: &Adapter) -> Option<(QuerySet, Buffer, Buffer)> {
-    let features = adapter.features();
-
-    if !features.contains(Features::TIMESTAMP_QUERY) {
-        return None;
+        shared: SharedResources {
+            render_pipeline,
+            target_format,
+            compute_pipelines: HashMap::from([(DEFAULT_WORKGROUP_SIZE, compute_pipeline)]),
+            layouts,
+            shader_modules,
+            texture_sampler,
+            shared_stage_data,
+            scene_revision: None,
+        },
+        viewports: HashMap::new(),
     }
-
-    let read_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
-        label: Some("Buffer for copying and reading the time information"),
-        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
-        contents: &[0; 16],
-    });
-
-    let write_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
-        label: Some("Buffer for querying the time"),
-        usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
-        contents: &[0; 16],
-    });
-
-    let query_set = device.create_query_set(&QuerySetDescriptor {
-        label: Some("Query set for a time stamp"),
-        count: 2,
-        ty: QueryType::Timestamp,
-    });
-
-    Some((query_set, read_buffer, write_buffer))
-}
-
-fn concat_bind_group_layouts<'a>(
-    layout1: &'a Vec<BindGroupLayout>,
-    layout2: &'a Vec<BindGroupLayout>,
-) -> Vec<&'a BindGroupLayout> {
-    let mut refs1: Vec<&BindGroupLayout> = layout1.iter().map(|layout| layout).collect();
-    let mut refs2: Vec<&BindGroupLayout> = layout2.iter().map(|layout| layout).collect();
-    refs1.append(&mut refs2);
-
-    refs1
 }
@@ -0,0 +1,164 @@
+use std::sync::mpsc;
+
+use eframe::egui_wgpu::wgpu::*;
+
+use super::render_stage::{get_render_pipeline, render_shader_source};
+use super::renderer::{PipelineBindings, SharedResources};
+use super::viewport::ViewportResources;
+
+/**
+ * Paints the image like it's shown, tonemapped and in srgb, and copies it back to the cpu,
+ * waiting for the gpu. Gives the rgba bytes of the image row after row
+ */
+pub fn read_display(
+    device: &Device,
+    queue: &Queue,
+    shared: &SharedResources,
+    viewport: &ViewportResources,
+) -> Vec<u8> {
+    let [width, height] = viewport.accumulated_size;
+    let format = TextureFormat::Rgba8Unorm;
+    let texture = device.create_texture(&TextureDescriptor {
+        dimension: TextureDimension::D2,
+        format,
+        label: Some("Texture for reading the displayed image"),
+        mip_level_count: 1,
+        sample_count: 1,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        view_formats: &[],
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    // the pipeline of the ui paints in the format of the window
+    // the modules are only kept when they could be put together, the format doesn't change that
+    let shader = render_shader_source(&shared.shader_modules, format)
+        .expect("the modules were put together when they were loaded");
+    let pipeline = get_render_pipeline(
+        device,
+        &shader,
+        format,
+        &PipelineBindings::RENDER.layouts(&shared.layouts),
+    );
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Encoder for painting the displayed image"),
+    });
+    {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Render pass for the displayed image"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        viewport.draw(&mut render_pass, &pipeline);
+    }
+    queue.submit(Some(encoder.finish()));
+
+    read_texture(device, queue, &texture, viewport.accumulated_size)
+}
+
+/**
+ * Copies the average of the samples back to the cpu, waiting for the gpu.
+ * Gives the rgba floats of the image row after row, in high dynamic range,
+ * or nothing when no sample was rendered yet
+ */
+pub fn read_radiance(
+    device: &Device,
+    queue: &Queue,
+    viewport: &ViewportResources,
+) -> Option<Vec<f32>> {
+    if viewport.sample_count == 0 {
+        return None;
+    }
+    let sums = read_texture(
+        device,
+        queue,
+        viewport.latest_accumulation(),
+        viewport.accumulated_size,
+    );
+    let sample_count = viewport.sample_count as f32;
+    Some(
+        bytemuck::cast_slice::<u8, [u8; 4]>(&sums)
+            .iter()
+            .map(|&sum| f32::from_ne_bytes(sum) / sample_count)
+            .collect(),
+    )
+}
+
+/**
+ * The pixels in the top left corner of a texture, without the padding the copy needs between the rows
+ */
+fn read_texture(device: &Device, queue: &Queue, texture: &Texture, size: [u32; 2]) -> Vec<u8> {
+    let [width, height] = size;
+    let bytes_per_pixel = texture
+        .format()
+        .block_size(None)
+        .expect("the textures that are read back have a single aspect");
+    let row_size = width * bytes_per_pixel;
+    // the rows of a copy have to be aligned
+    let padded_row_size =
+        row_size.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Buffer for reading a texture"),
+        size: (padded_row_size * height) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Encoder for reading a texture"),
+    });
+    // the texture can be bigger than the image
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_size),
+                rows_per_image: Some(height),
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = mpsc::channel();
+    buffer.slice(..).map_async(MapMode::Read, move |result| {
+        sender.send(result).ok();
+    });
+    device.poll(Maintain::Wait);
+    receiver
+        .recv()
+        .expect("the buffer is mapped once the gpu is done")
+        .expect("couldn't map the buffer of the texture");
+
+    let pixels = {
+        let view = buffer.slice(..).get_mapped_range();
+        view.chunks(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect()
+    }; // have to drop the view into the buffer before unmapping
+    buffer.unmap();
+    pixels
+}
@@ -1,68 +1,148 @@
 use eframe::egui_wgpu::wgpu::*;
 
-use crate::ray_tracer::camera::{OUTPUT_TEXTURE_HEIGHT, OUTPUT_TEXTURE_WIDTH};
+use super::bind_groups::{BindGroupBuilder, BindGroupSchema};
+use super::shader_modules::{ComposedShader, ShaderError, ShaderModules};
+use super::shared_stage_data::SharedStageUniform;
+use super::wgsl::{wgsl_struct, with_declarations, WgslStruct};
 
-pub type RenderBindGroups = Vec<BindGroup>;
-pub type RenderBindGroupLayout = Vec<BindGroupLayout>;
+/**
+ * The curves that bring the radiance, which has no upper limit, between 0 and 1 to display it
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// everything brighter than 1 is white
+    Clamp,
+    Reinhard,
+    /// the filmic curve of the Academy Color Encoding System, fitted by Krzysztof Narkowicz
+    #[default]
+    Aces,
+    /// desaturates the bright colors instead of shifting their hue
+    AgX,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 4] = [
+        Tonemapper::Clamp,
+        Tonemapper::Reinhard,
+        Tonemapper::Aces,
+        Tonemapper::AgX,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tonemapper::Clamp => "clamp",
+            Tonemapper::Reinhard => "reinhard",
+            Tonemapper::Aces => "aces",
+            Tonemapper::AgX => "agx",
+        }
+    }
+}
+
+/**
+ * How the image is displayed, changing it doesn't restart the accumulation
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DisplaySettings {
+    /// in stops, the radiance is multiplied by 2 to the power of the exposure
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+}
+
+wgsl_struct! {
+    /**
+     * The display settings like the fragment shader wants them
+     */
+    pub struct DisplayUniform as Display {
+        /// in stops
+        pub exposure: f32,
+        /// one of the TONEMAPPER constants, in the order of Tonemapper::ALL
+        pub tonemapper: u32,
+        pub _padding: [u32; 2],
+    }
+}
+
+impl From<&DisplaySettings> for DisplayUniform {
+    fn from(settings: &DisplaySettings) -> Self {
+        DisplayUniform {
+            exposure: settings.exposure,
+            tonemapper: settings.tonemapper as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+pub fn get_display_buffer(device: &Device) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("The buffer containing the display settings"),
+        size: std::mem::size_of::<DisplayUniform>() as u64,
+        mapped_at_creation: false,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    })
+}
+
+/**
+ * The image of a viewport and how it's displayed
+ */
+pub fn get_render_bind_group_schema(device: &Device) -> BindGroupSchema {
+    BindGroupBuilder::new("Fragment bind group")
+        .texture(ShaderStages::FRAGMENT | ShaderStages::VERTEX, true)
+        .sampler(ShaderStages::FRAGMENT)
+        .uniform::<DisplayUniform>(ShaderStages::FRAGMENT)
+        .build(device)
+}
 
-pub fn get_render_bind_group(
+/**
+ * Creates the bind group of a viewport, it's created again when its output texture is resized
+ */
+pub fn create_render_bind_group(
     device: &Device,
+    schema: &BindGroupSchema,
     view: &TextureView,
-) -> (RenderBindGroupLayout, RenderBindGroups) {
-    let texture_sampler = device.create_sampler(&SamplerDescriptor {
-        label: Some("Sampler for the fragment texture"),
-        mag_filter: FilterMode::Nearest, // the way to scale up a pixel in the texture. Take the nearest pixel or linearly interpolate between pixels
-        min_filter: FilterMode::Nearest, // the way to scale up a pixel in the texture
-        ..Default::default()
-    });
-
-    let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("Fragment bind group layout"),
-        entries: &[
-            // texture
-            BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT | ShaderStages::VERTEX,
-                ty: BindingType::Texture {
-                    sample_type: TextureSampleType::Float { filterable: true },
-                    view_dimension: TextureViewDimension::D2,
-                    multisampled: false,
-                },
-                count: None,
-            },
-            // sampler
-            BindGroupLayoutEntry {
-                binding: 1,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
-                count: None,
-            },
+    texture_sampler: &Sampler,
+    display_buffer: &Buffer,
+) -> BindGroup {
+    schema.create_bind_group(
+        device,
+        &[
+            BindingResource::TextureView(view),
+            BindingResource::Sampler(texture_sampler),
+            display_buffer.as_entire_binding(),
         ],
-    });
+    )
+}
 
-    let bind_group = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("fragment bind group"),
-        layout: &bind_group_layout,
-        entries: &[
-            BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(view),
-            },
-            BindGroupEntry {
-                binding: 1,
-                resource: BindingResource::Sampler(&texture_sampler),
-            },
+/**
+ * The code of the render shader with the structs of the uniforms, for a target of this format
+ */
+pub fn render_shader_source(
+    modules: &ShaderModules,
+    target_format: TextureFormat,
+) -> Result<ComposedShader, ShaderError> {
+    // a srgb target encodes what the shader gives, encoding it in the shader too would do it twice
+    let mut shader = modules.shader_source(
+        "render.wgsl",
+        &[("ENCODE_SRGB", (!target_format.is_srgb()).to_string())],
+    )?;
+    shader.code = with_declarations(
+        &shader.code,
+        &[
+            SharedStageUniform::declaration(),
+            DisplayUniform::declaration(),
         ],
-    });
-    (vec![bind_group_layout], vec![bind_group])
+    );
+    Ok(shader)
 }
 
 pub fn get_render_pipeline(
     device: &Device,
-    render_state: &eframe::egui_wgpu::RenderState,
+    shader: &ComposedShader,
+    target_format: TextureFormat,
     bind_group_layouts: &[&BindGroupLayout],
 ) -> RenderPipeline {
-    let shader = device.create_shader_module(include_wgsl!("../shaders/render.wgsl"));
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("render.wgsl"),
+        source: ShaderSource::Wgsl(shader.code.as_str().into()),
+    });
 
     let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: Some("The layout of the pipeline"),
@@ -81,7 +161,7 @@ pub fn get_render_pipeline(
         fragment: Some(FragmentState {
             module: &shader,
             entry_point: "fragment_main",
-            targets: &[Some(render_state.target_format.into())],
+            targets: &[Some(target_format.into())],
         }),
         primitive: PrimitiveState::default(),
         depth_stencil: None,
@@ -90,8 +170,22 @@ pub fn get_render_pipeline(
     })
 }
 
-pub fn get_output_texture(device: &Device) -> (Texture, TextureView) {
-    let texture_format = TextureFormat::Rgba8Unorm;
+pub fn get_texture_sampler(device: &Device) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some("Sampler for the fragment texture"),
+        // the image is smaller than the viewport when the render scale is under 100%,
+        // interpolating between pixels looks better than big square pixels
+        mag_filter: FilterMode::Linear, // the way to scale up a pixel in the texture. Take the nearest pixel or linearly interpolate between pixels
+        min_filter: FilterMode::Linear, // the way to scale down the texture
+        ..Default::default()
+    })
+}
+
+/// the average of the samples, the radiance isn't limited to 1 until it's tonemapped
+pub const OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+pub fn get_output_texture(device: &Device, size: [u32; 2]) -> (Texture, TextureView) {
+    let texture_format = OUTPUT_FORMAT;
     let texture = device.create_texture(&TextureDescriptor {
         dimension: TextureDimension::D2,
         format: texture_format,
@@ -99,11 +193,12 @@ pub fn get_output_texture(device: &Device) -> (Texture, TextureView) {
         mip_level_count: 1,
         sample_count: 1,
         usage: TextureUsages::COPY_DST
+            | TextureUsages::COPY_SRC
             | TextureUsages::STORAGE_BINDING
             | TextureUsages::TEXTURE_BINDING,
         size: Extent3d {
-            width: OUTPUT_TEXTURE_WIDTH,
-            height: OUTPUT_TEXTURE_HEIGHT,
+            width: size[0],
+            height: size[1],
             depth_or_array_layers: 1,
         },
         view_formats: &[],
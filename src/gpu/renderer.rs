@@ -1,119 +1,371 @@
-use std::sync::{atomic::AtomicU64, Arc};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-use eframe::egui::Vec2;
+use eframe::egui::{self, Vec2};
 use eframe::egui_wgpu::{self, wgpu::*};
+use web_time::Instant;
 
-use crate::ray_tracer::camera::{
-    OUTPUT_TEXTURE_DIMENTIONS, OUTPUT_TEXTURE_HEIGHT, OUTPUT_TEXTURE_WIDTH,
+use crate::ray_tracer::camera::Camera;
+use crate::ray_tracer::scene::Scene;
+
+use super::bind_groups::{BindGroupSchema, SetBindGroups};
+use super::compute_stage::{compute_shader_source, get_compute_pipeline};
+use super::render_stage::DisplaySettings;
+use super::shader_modules::ShaderModules;
+use super::shared_stage_data::{write_scene, SharedStageData};
+use super::time_query::{TimeHistory, TimingSource};
+use super::viewport::ViewportResources;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use super::{
+    render_stage::{get_render_pipeline, render_shader_source},
+    shader_modules::{ComposedShader, ShaderError, SHADERS_DIRECTORY},
+    shader_reload::check_shader,
 };
 
-use super::compute_stage::ComputeBindGroups;
-use super::render_stage::RenderBindGroups;
-use super::shared_stage_data::{SharedStageBindGroup, SharedStageData, SharedStageUniform};
+/**
+ * The layouts of the bind groups of every stage, each viewport creates its bind groups with them
+ */
+pub struct BindGroupLayouts {
+    pub compute: BindGroupSchema,
+    pub render: BindGroupSchema,
+    pub shared_stage: BindGroupSchema,
+}
 
-pub struct RenderResources {
+impl BindGroupLayouts {
+    pub fn schema(&self, slot: BindGroupSlot) -> &BindGroupSchema {
+        match slot {
+            BindGroupSlot::Compute => &self.compute,
+            BindGroupSlot::Render => &self.render,
+            BindGroupSlot::SharedStage => &self.shared_stage,
+        }
+    }
+}
+
+/**
+ * The bind groups a pipeline can use, every viewport has one of each
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindGroupSlot {
+    /// the output texture and the accumulation textures being written
+    Compute,
+    /// the output texture with the display settings
+    Render,
+    /// the scene with the uniforms of the viewport
+    SharedStage,
+}
+
+/**
+ * The bind groups of a pipeline in the order of their indices.
+ * The pipeline is created with their layouts and its passes set them from this same order
+ */
+pub struct PipelineBindings {
+    slots: [BindGroupSlot; 2],
+}
+
+impl PipelineBindings {
+    pub const COMPUTE: PipelineBindings = PipelineBindings {
+        slots: [BindGroupSlot::Compute, BindGroupSlot::SharedStage],
+    };
+    pub const RENDER: PipelineBindings = PipelineBindings {
+        slots: [BindGroupSlot::Render, BindGroupSlot::SharedStage],
+    };
+
+    /// the layouts the pipeline is created with
+    pub fn layouts<'a>(&self, layouts: &'a BindGroupLayouts) -> [&'a BindGroupLayout; 2] {
+        self.slots.map(|slot| &layouts.schema(slot).layout)
+    }
+
+    /// sets the bind groups of a viewport in a pass using the pipeline
+    pub fn set_bind_groups<'a>(
+        &self,
+        pass: &mut impl SetBindGroups<'a>,
+        viewport: &'a ViewportResources,
+    ) {
+        pass.set_bind_groups(&self.slots.map(|slot| viewport.bind_group(slot)));
+    }
+}
+
+/**
+ * What every viewport uses, the pipelines and the scene
+ */
+pub struct SharedResources {
     pub render_pipeline: RenderPipeline,
-    pub compute_pipeline: ComputePipeline,
-    pub render_bind_groups: RenderBindGroups,
-    pub compute_bind_groups: ComputeBindGroups,
+    /// the format of the texture the image is painted on, to create the render pipeline again
+    pub target_format: TextureFormat,
+    /// created the first time a viewport uses a workgroup size
+    pub compute_pipelines: HashMap<[u32; 2], ComputePipeline>,
+    pub layouts: BindGroupLayouts,
+    /// the code the pipelines are made from, the last one that worked when the shaders are reloaded
+    pub shader_modules: ShaderModules,
+    pub texture_sampler: Sampler,
     pub shared_stage_data: SharedStageData,
-    pub shared_stage_bind_groups: SharedStageBindGroup,
-    pub time_query: Option<(QuerySet, Buffer, Buffer)>,
+    /// the revision of the scene currently in the gpu buffers
+    pub scene_revision: Option<u64>,
 }
 
-pub struct RenderCallBack {
-    pub render_time: Arc<AtomicU64>,
-    pub output_size: Vec2,
+pub struct RenderResources {
+    pub shared: SharedResources,
+    /// each viewport has its own image of the scene
+    pub viewports: HashMap<egui::Id, ViewportResources>,
 }
 
-impl egui_wgpu::CallbackTrait for RenderCallBack {
-    fn prepare(
-        &self,
-        _device: &Device,
-        queue: &Queue,
-        encoder: &mut CommandEncoder,
-        resources: &mut egui_wgpu::CallbackResources,
-    ) -> Vec<CommandBuffer> {
-        let resources: &RenderResources = resources.get().unwrap();
-        if let Some((query, _, _)) = &resources.time_query {
-            // write the query before computing
-            encoder.write_timestamp(query, 0);
+/**
+ * The resources of every widget by its id, egui only keeps one value of each type for the callbacks
+ * and every widget has its own pipelines and scene
+ */
+#[derive(Default)]
+pub struct WidgetResources {
+    pub widgets: HashMap<u64, RenderResources>,
+}
+
+impl WidgetResources {
+    /// the resources of a widget, they're there from when it's created until it's dropped
+    pub fn get(
+        callback_resources: &egui_wgpu::CallbackResources,
+        widget_id: u64,
+    ) -> Option<&RenderResources> {
+        let resources: &WidgetResources = callback_resources.get()?;
+        resources.widgets.get(&widget_id)
+    }
+
+    pub fn get_mut(
+        callback_resources: &mut egui_wgpu::CallbackResources,
+        widget_id: u64,
+    ) -> Option<&mut RenderResources> {
+        let resources: &mut WidgetResources = callback_resources.get_mut()?;
+        resources.widgets.get_mut(&widget_id)
+    }
+}
+
+/// the revisions of every scene come from here, so two scenes never have the same one
+static NEXT_SCENE_REVISION: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * A revision no scene had before, to give with a scene after it changed
+ */
+pub fn new_scene_revision() -> u64 {
+    NEXT_SCENE_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+impl SharedResources {
+    /**
+     * Creates the compute pipeline with this workgroup size if no viewport used it yet
+     */
+    pub fn prepare_compute_pipeline(&mut self, device: &Device, workgroup_size: [u32; 2]) {
+        if self.compute_pipelines.contains_key(&workgroup_size) {
+            return;
         }
-        queue.write_buffer(
-            &resources.shared_stage_data.size_update_buffer,
-            0,
-            bytemuck::cast_slice(&[SharedStageUniform {
-                size: self.output_size.min(OUTPUT_TEXTURE_DIMENTIONS).into(),
-            }]),
+        // the modules are only kept when they could be put together, the workgroup size doesn't change that
+        let shader = compute_shader_source(&self.shader_modules, workgroup_size)
+            .expect("the modules were put together when they were loaded");
+        let pipeline = get_compute_pipeline(
+            device,
+            &shader,
+            &PipelineBindings::COMPUTE.layouts(&self.layouts),
         );
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Compute pass"),
-            });
-            compute_pass.set_pipeline(&resources.compute_pipeline);
-            resources.compute_bind_groups.iter().enumerate().for_each(
-                |(index, compute_bind_group)| {
-                    compute_pass.set_bind_group(index as u32, compute_bind_group, &[]);
-                },
-            );
+        self.compute_pipelines.insert(workgroup_size, pipeline);
+    }
+
+    /**
+     * Creates the pipelines again from the modules in the shaders directory.
+     * They're checked before, the pipelines are kept as they were when something is wrong
+     */
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shaders(&mut self, device: &Device) -> Result<(), Vec<ShaderError>> {
+        let modules = ShaderModules::read(std::path::Path::new(SHADERS_DIRECTORY))
+            .map_err(|message| vec![ShaderError::new(message)])?;
+        // every error is shown, not only the first one
+        let mut errors = Vec::new();
+        let mut checked = |name, shader: Result<ComposedShader, ShaderError>| {
+            let shader = shader.and_then(|shader| check_shader(name, &shader).map(|_| shader));
+            shader.map_err(|error| errors.push(error)).ok()
+        };
+        let render_shader = checked(
+            "render.wgsl",
+            render_shader_source(&modules, self.target_format),
+        );
+        let compute_shaders: Vec<_> = self
+            .compute_pipelines
+            .keys()
+            .filter_map(|&workgroup_size| {
+                let shader = compute_shader_source(&modules, workgroup_size);
+                Some((workgroup_size, checked("raytracing.wgsl", shader)?))
+            })
+            .collect();
+        let Some(render_shader) = render_shader.filter(|_| errors.is_empty()) else {
+            return Err(errors);
+        };
 
-            let compute_group_length = resources.compute_bind_groups.len();
-
-            resources
-                .shared_stage_bind_groups
-                .iter()
-                .enumerate()
-                .for_each(|(index, shared_bind_group)| {
-                    let index = (index + compute_group_length) as u32;
-                    compute_pass.set_bind_group(index, shared_bind_group, &[]);
-                });
-
-            // since the workgroups are 16x16, then the pixels are divided by 16;
-            let width = ((self.output_size.x / 16.0) as u32).min(OUTPUT_TEXTURE_WIDTH);
-            let height = ((self.output_size.y / 16.0) as u32).min(OUTPUT_TEXTURE_HEIGHT);
-            compute_pass.dispatch_workgroups(width, height, 1);
+        // naga doesn't know the bind groups, wgpu would panic on the ones that don't match
+        device.push_error_scope(ErrorFilter::Validation);
+        let render_pipeline = get_render_pipeline(
+            device,
+            &render_shader,
+            self.target_format,
+            &PipelineBindings::RENDER.layouts(&self.layouts),
+        );
+        let compute_pipelines: HashMap<_, _> = compute_shaders
+            .iter()
+            .map(|(workgroup_size, shader)| {
+                let pipeline = get_compute_pipeline(
+                    device,
+                    shader,
+                    &PipelineBindings::COMPUTE.layouts(&self.layouts),
+                );
+                (*workgroup_size, pipeline)
+            })
+            .collect();
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(vec![ShaderError {
+                location: None,
+                message: "wgpu couldn't create the pipelines".to_string(),
+                details: error.to_string(),
+            }]);
         }
+        self.render_pipeline = render_pipeline;
+        self.compute_pipelines = compute_pipelines;
+        self.shader_modules = modules;
+        Ok(())
+    }
+}
 
-        // well let's hope this works
-        if let Some((query, _, _)) = &resources.time_query {
-            encoder.write_timestamp(query, 1);
+impl RenderResources {
+    /**
+     * Uploads the scene when its revision changed, the viewports get new bind groups
+     * when a buffer had to be recreated
+     */
+    pub fn upload_scene(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        scene_revision: u64,
+    ) {
+        if self.shared.scene_revision == Some(scene_revision) {
+            return;
+        }
+        let recreated = write_scene(device, queue, &mut self.shared.shared_stage_data, scene);
+        if recreated {
+            for viewport in self.viewports.values_mut() {
+                viewport.recreate_shared_stage_bind_group(device, &self.shared);
+            }
         }
+        self.shared.scene_revision = Some(scene_revision);
+    }
 
-        Vec::new()
+    /**
+     * Reloads the shaders, the images of the viewports start again with the new ones
+     */
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shaders(&mut self, device: &Device) -> Result<(), Vec<ShaderError>> {
+        self.shared.reload_shaders(device)?;
+        for viewport in self.viewports.values_mut() {
+            viewport.restart_image();
+        }
+        Ok(())
     }
+}
+
+pub struct RenderCallBack {
+    /// the resources of the widget painting the viewport are found with it
+    pub widget_id: u64,
+    /// the resources of the viewport are found with it
+    pub viewport_id: egui::Id,
+    /// the render times in milliseconds, given back to the ui
+    pub render_times: Arc<Mutex<TimeHistory>>,
+    /// the size of the viewport in points
+    pub output_size: Vec2,
+    /// prepare doesn't get the PaintCallbackInfo, so this is the pixels per point it would have
+    /// to render in physical pixels like the viewport it gives to paint
+    pub pixels_per_point: f32,
+    /// the fraction of the physical pixels that are rendered, the image is scaled to fill the viewport
+    pub render_scale: f32,
+    pub scene: Arc<Scene>,
+    /// changes every time the scene is modified so the buffers are only rewritten when needed,
+    /// it comes from new_scene_revision
+    pub scene_revision: u64,
+    /// the camera is given separately from the scene since it changes a lot more
+    pub camera: Camera,
+    /// the size of the workgroups of the compute shader, each size has its own pipeline
+    pub workgroup_size: [u32; 2],
+    /// the rendering stops once this many samples are added up
+    pub samples_target: u32,
+    /// how the image is displayed, it can change without restarting the accumulation
+    pub display: DisplaySettings,
+    /// the number of samples in the image, given back to the ui
+    pub sample_count: Arc<AtomicU32>,
+    /// to keep painting until the samples target is reached
+    pub context: egui::Context,
+}
 
-    fn finish_prepare(
+impl egui_wgpu::CallbackTrait for RenderCallBack {
+    fn prepare(
         &self,
         device: &Device,
         queue: &Queue,
         encoder: &mut CommandEncoder,
         resources: &mut egui_wgpu::CallbackResources,
     ) -> Vec<CommandBuffer> {
-        let resources: &RenderResources = resources.get().unwrap();
-
-        // this doesn't have to be here and could could be done in the
-        // reads the buffer and stores the render time
-        // Since the render pass hasn't passed yet, it will read 0 for the first frame and then the previous frame render time
-        if let Some((query, read_buffer, query_buffer)) = &resources.time_query {
-            encoder.resolve_query_set(query, 0..2, &query_buffer, 0);
-            encoder.copy_buffer_to_buffer(query_buffer, 0, read_buffer, 0, read_buffer.size());
-
-            let buffer_slice = read_buffer.slice(..);
-            buffer_slice.map_async(MapMode::Read, |_| {});
-
-            // wait for the map to be mapped
-            device.poll(Maintain::Wait);
-
-            let period = queue.get_timestamp_period();
-            let time_stamp_raw = buffer_slice.get_mapped_range();
-            let time_stamp_data: &[u64] = bytemuck::cast_slice(&*time_stamp_raw);
-            let time = (time_stamp_data[1] - time_stamp_data[0]) as f64 * period as f64 * 1e-6;
-            self.render_time
-                .store(time.to_bits(), std::sync::atomic::Ordering::SeqCst);
-
-            drop(time_stamp_raw); // have to drop the view into the buffer before unmapping
-            read_buffer.unmap();
+        // the widget can be dropped in the frame it was painted
+        let Some(resources) = WidgetResources::get_mut(resources, self.widget_id) else {
+            return Vec::new();
+        };
+        resources
+            .shared
+            .prepare_compute_pipeline(device, self.workgroup_size);
+        resources.upload_scene(device, queue, &self.scene, self.scene_revision);
+
+        let RenderResources { shared, viewports } = resources;
+        let viewport = viewports
+            .entry(self.viewport_id)
+            .or_insert_with(|| ViewportResources::new(device, shared));
+        // the times of the previous frames that are ready
+        if let Some(time_query) = &mut viewport.time_query {
+            let times = time_query.harvest(device, queue);
+            let mut render_times = self.render_times.lock().unwrap();
+            render_times.source = TimingSource::GpuTimestamps;
+            times.into_iter().for_each(|time| render_times.push(time));
+        }
+
+        let image_size = image_size(
+            self.output_size * self.pixels_per_point * self.render_scale,
+            device.limits().max_texture_dimension_2d,
+        );
+        viewport.fit_textures(device, shared, image_size);
+        viewport.workgroup_size = self.workgroup_size;
+        viewport.set_display(queue, &self.display);
+        viewport.start_image(
+            queue,
+            &self.scene,
+            self.scene_revision,
+            &self.camera,
+            image_size,
+        );
+
+        viewport.rendering = viewport.sample_count < self.samples_target;
+        if !viewport.rendering {
+            self.sample_count
+                .store(viewport.sample_count, Ordering::Relaxed);
+            viewport.last_frame = None;
+            return Vec::new();
+        }
+        if viewport.time_query.is_none() {
+            let now = Instant::now();
+            if let Some(last_frame) = viewport.last_frame {
+                let mut render_times = self.render_times.lock().unwrap();
+                render_times.source = TimingSource::CpuFrameTime;
+                render_times.push((now - last_frame).as_secs_f64() * 1000.0);
+            }
+            viewport.last_frame = Some(now);
         }
+
+        viewport.render_sample(queue, encoder, shared, self.scene.settings.max_depth);
+        self.sample_count
+            .store(viewport.sample_count, Ordering::Relaxed);
+        // paint once more after the last sample so the ui shows the final count
+        self.context.request_repaint();
+
         Vec::new()
     }
 
@@ -123,26 +375,114 @@ impl egui_wgpu::CallbackTrait for RenderCallBack {
         render_pass: &mut RenderPass<'rp>,
         callback_resources: &'rp egui_wgpu::CallbackResources,
     ) {
-        let resources: &RenderResources = callback_resources.get().unwrap();
+        let Some(resources) = WidgetResources::get(callback_resources, self.widget_id) else {
+            return;
+        };
+        let viewport = &resources.viewports[&self.viewport_id];
+        viewport.draw(render_pass, &resources.shared.render_pipeline);
+    }
+}
 
-        render_pass.set_pipeline(&resources.render_pipeline);
-        resources
-            .render_bind_groups
-            .iter()
-            .enumerate()
-            .for_each(|(index, render_bind_group)| {
-                render_pass.set_bind_group(index as u32, render_bind_group, &[]);
-            });
-        let render_group_length = resources.render_bind_groups.len();
+/**
+ * A seed that is different every time, from the clock since it's the only source of randomness around
+ */
+pub fn random_seed() -> u32 {
+    web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos())
+}
 
-        resources
-            .shared_stage_bind_groups
-            .iter()
-            .enumerate()
-            .for_each(|(index, shared_bind_group)| {
-                let index = (index + render_group_length) as u32;
-                render_pass.set_bind_group(index, shared_bind_group, &[]);
-            });
-        render_pass.draw(0..6, 0..1);
+/// the textures grow by steps of this many pixels so resizing the window doesn't recreate them every frame
+pub const TEXTURE_SIZE_STEP: u32 = 256;
+
+/**
+ * The size of the image in pixels, a texture can't be empty or bigger than the limit of the device
+ */
+fn image_size(output_size: Vec2, max_texture_dimension: u32) -> [u32; 2] {
+    [output_size.x, output_size.y].map(|size| (size.round() as u32).clamp(1, max_texture_dimension))
+}
+
+/**
+ * The textures only grow to the next step when the image doesn't fit
+ * and only shrink when the image is less than half of them
+ */
+pub fn texture_size(
+    current: [u32; 2],
+    image_size: [u32; 2],
+    max_texture_dimension: u32,
+) -> [u32; 2] {
+    let resize =
+        (0..2).any(|axis| image_size[axis] > current[axis] || image_size[axis] < current[axis] / 2);
+    if !resize {
+        return current;
+    }
+    image_size.map(|size| {
+        (size.div_ceil(TEXTURE_SIZE_STEP) * TEXTURE_SIZE_STEP).min(max_texture_dimension)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::egui::Vec2;
+    use eframe::egui_wgpu::CallbackResources;
+
+    use super::{image_size, new_scene_revision, texture_size, WidgetResources};
+
+    #[test]
+    fn images_are_rounded_and_clamped_to_the_device() {
+        for (output_size, max_texture_dimension, expected) in [
+            (Vec2::new(640.4, 480.6), 8192, [640, 481]),
+            (Vec2::new(10000.0, 300.0), 8192, [8192, 300]),
+            // a viewport with nothing to show still needs a texture
+            (Vec2::ZERO, 8192, [1, 1]),
+        ] {
+            assert_eq!(
+                image_size(output_size, max_texture_dimension),
+                expected,
+                "{:?}",
+                output_size
+            );
+        }
+    }
+
+    #[test]
+    fn textures_are_only_resized_when_the_image_is_too_big_or_small() {
+        for (name, current, image, max_texture_dimension, expected) in [
+            ("growing", [256, 256], [300, 100], 8192, [512, 256]),
+            (
+                "between half and full",
+                [512, 512],
+                [300, 256],
+                8192,
+                [512, 512],
+            ),
+            ("full", [512, 512], [512, 512], 8192, [512, 512]),
+            ("below half", [1024, 512], [300, 300], 8192, [512, 512]),
+            ("clamped", [256, 256], [4000, 100], 4000, [4000, 256]),
+            ("0x0 viewport", [512, 512], [1, 1], 8192, [256, 256]),
+        ] {
+            assert_eq!(
+                texture_size(current, image, max_texture_dimension),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn a_missing_widget_has_no_resources() {
+        let mut callback_resources = CallbackResources::default();
+        assert!(WidgetResources::get(&callback_resources, 0).is_none());
+        callback_resources.insert(WidgetResources::default());
+        assert!(WidgetResources::get(&callback_resources, 0).is_none());
+        assert!(WidgetResources::get_mut(&mut callback_resources, 0).is_none());
+    }
+
+    #[test]
+    fn scene_revisions_are_never_given_twice() {
+        let first = new_scene_revision();
+        let second = new_scene_revision();
+        assert!(second > first);
     }
 }
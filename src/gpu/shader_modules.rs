@@ -0,0 +1,341 @@
+use std::borrow::Cow;
+
+/// every file of src/shaders by the name it's included with, they're in the binary
+/// so the shaders can be put together on the web too, where there are no files to read
+pub const MODULES: [(&str, &str); 10] = [
+    (
+        "raytracing.wgsl",
+        include_str!("../shaders/raytracing.wgsl"),
+    ),
+    ("render.wgsl", include_str!("../shaders/render.wgsl")),
+    (
+        "shared_stage.wgsl",
+        include_str!("../shaders/shared_stage.wgsl"),
+    ),
+    ("ray.wgsl", include_str!("../shaders/ray.wgsl")),
+    ("camera.wgsl", include_str!("../shaders/camera.wgsl")),
+    ("rng.wgsl", include_str!("../shaders/rng.wgsl")),
+    ("sampling.wgsl", include_str!("../shaders/sampling.wgsl")),
+    ("shapes.wgsl", include_str!("../shaders/shapes.wgsl")),
+    ("materials.wgsl", include_str!("../shaders/materials.wgsl")),
+    ("color.wgsl", include_str!("../shaders/color.wgsl")),
+];
+
+const INCLUDE_INSTRUCTION: &str = "//!include";
+const DEFINE_INSTRUCTION: &str = "//!define";
+
+/// the directory the modules are read from to reload them, the one they are built from
+#[cfg(any(test, all(debug_assertions, not(target_arch = "wasm32"))))]
+pub const SHADERS_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+/**
+ * Where an error is in the modules, the line and column are counted from 1
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderLocation {
+    pub module: &'static str,
+    pub line: usize,
+    pub column: usize,
+}
+
+/**
+ * Why a shader couldn't be put together or compiled
+ */
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+    /// none when the error isn't in the code of a module, like a file that can't be read
+    pub location: Option<ShaderLocation>,
+    pub message: String,
+    /// the message of the compiler with the code around the error, its lines are the ones of the whole shader
+    pub details: String,
+}
+
+impl ShaderError {
+    pub fn new(message: String) -> Self {
+        ShaderError {
+            location: None,
+            message,
+            details: String::new(),
+        }
+    }
+
+    fn at(location: ShaderLocation, message: String) -> Self {
+        ShaderError {
+            location: Some(location),
+            ..ShaderError::new(message)
+        }
+    }
+}
+
+/**
+ * The code of the modules the shaders are put together from
+ */
+pub struct ShaderModules {
+    /// in the order of MODULES
+    sources: Vec<Cow<'static, str>>,
+}
+
+/**
+ * The code of a shader with its modules, keeping where each line comes from
+ */
+#[derive(Debug)]
+pub struct ComposedShader {
+    pub code: String,
+    /// the module and the line in it of every line of the code
+    lines: Vec<(&'static str, usize)>,
+}
+
+impl ComposedShader {
+    /// the module and the line in it of a line of the code, the lines are counted from 1.
+    /// There is none for the lines added after the modules, the structs declared from the rust ones
+    pub fn origin(&self, line: usize) -> Option<(&'static str, usize)> {
+        self.lines.get(line.checked_sub(1)?).copied()
+    }
+}
+
+impl ShaderModules {
+    /// the modules built in the binary
+    pub fn embedded() -> Self {
+        ShaderModules {
+            sources: MODULES
+                .iter()
+                .map(|(_, source)| Cow::Borrowed(*source))
+                .collect(),
+        }
+    }
+
+    /**
+     * The modules as they are now in a directory, so they can be changed without building again
+     */
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn read(directory: &std::path::Path) -> Result<Self, String> {
+        let sources = MODULES
+            .iter()
+            .map(|(name, _)| {
+                std::fs::read_to_string(directory.join(name))
+                    .map(Cow::Owned)
+                    .map_err(|error| format!("can't read {}: {}", name, error))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ShaderModules { sources })
+    }
+
+    /**
+     * The code of a shader with its modules, in the syntax of wgsl_preprocessor:
+     * a line `//!include a.wgsl b.wgsl` is replaced by the code of those modules
+     * and `//!define NAME value` replaces NAME in the module and the ones including it.
+     * The defines given here toggle the features of the shader when the pipeline is created,
+     * they replace the value a module defines them with.
+     * The modules don't include each other, the shaders include everything they use so nothing is there twice
+     */
+    pub fn shader_source(
+        &self,
+        root: &str,
+        defines: &[(&str, String)],
+    ) -> Result<ComposedShader, ShaderError> {
+        let (shader, definitions) = self.load_module(root, defines, &mut Vec::new())?;
+        for (name, _) in defines {
+            if !definitions.iter().any(|(defined, _)| defined == name) {
+                return Err(ShaderError::new(format!(
+                    "{}: no module defines {}, the pipelines need it",
+                    root, name
+                )));
+            }
+        }
+        Ok(shader)
+    }
+
+    fn module(&self, name: &str) -> Option<(&'static str, &str)> {
+        let index = MODULES.iter().position(|(module, _)| *module == name)?;
+        Some((MODULES[index].0, &self.sources[index]))
+    }
+
+    /**
+     * The code of the module with its includes, and its defines which are also replaced in the modules including it.
+     * The modules being loaded are kept to stop at an include that would load one of them again forever
+     */
+    fn load_module(
+        &self,
+        name: &str,
+        defines: &[(&str, String)],
+        loading: &mut Vec<&'static str>,
+    ) -> Result<(ComposedShader, Vec<(String, String)>), ShaderError> {
+        let (name, source) = self
+            .module(name)
+            .ok_or_else(|| ShaderError::new(format!("there is no shader module named {}", name)))?;
+        loading.push(name);
+        let mut shader = ComposedShader {
+            code: String::new(),
+            lines: Vec::new(),
+        };
+        let mut definitions = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            if line.starts_with(INCLUDE_INSTRUCTION) {
+                for include in line.split_whitespace().skip(1) {
+                    let location = ShaderLocation {
+                        module: name,
+                        line: index + 1,
+                        // the include is a part of the line
+                        column: include.as_ptr() as usize - line.as_ptr() as usize + 1,
+                    };
+                    if self.module(include).is_none() {
+                        return Err(ShaderError::at(
+                            location,
+                            format!("there is no shader module named {}", include),
+                        ));
+                    }
+                    if let Some(first) = loading.iter().position(|module| *module == include) {
+                        let cycle = loading[first..].join(" includes ");
+                        return Err(ShaderError::at(
+                            location,
+                            format!("{} includes {} again", cycle, include),
+                        ));
+                    }
+                    let (included, included_definitions) =
+                        self.load_module(include, defines, loading)?;
+                    shader.code += &included.code;
+                    shader.lines.extend(included.lines);
+                    definitions.extend(included_definitions);
+                }
+            } else if let Some((defined, mut value)) = definition(line) {
+                if let Some((_, chosen)) = defines.iter().find(|(name, _)| *name == defined) {
+                    value = chosen.clone();
+                }
+                definitions.push((defined, value));
+            } else {
+                shader.code += line;
+                shader.code += "\n";
+                shader.lines.push((name, index + 1));
+            }
+        }
+        for (name, value) in &definitions {
+            shader.code = replace_word(&shader.code, name, value);
+        }
+        loading.pop();
+        Ok((shader, definitions))
+    }
+}
+
+/// the name and value of a `//!define NAME value` line
+fn definition(line: &str) -> Option<(String, String)> {
+    let (_, definition) = line.split_once(DEFINE_INSTRUCTION)?;
+    let (name, value) = definition.strip_prefix(' ')?.split_once(' ')?;
+    (!name.is_empty() && !value.is_empty()).then(|| (name.to_string(), value.to_string()))
+}
+
+/// only whole names are replaced, so a define doesn't change the names that contain it
+fn replace_word(source: &str, name: &str, value: &str) -> String {
+    let mut replaced = String::with_capacity(source.len());
+    let mut copied = 0;
+    for (index, _) in source.match_indices(name) {
+        if is_word(source, index, name) {
+            replaced += &source[copied..index];
+            replaced += value;
+            copied = index + name.len();
+        }
+    }
+    replaced + &source[copied..]
+}
+
+fn is_word(source: &str, index: usize, name: &str) -> bool {
+    let is_name_char = |char: char| char.is_alphanumeric() || char == '_';
+    let before = source[..index].chars().next_back();
+    let after = source[index + name.len()..].chars().next();
+    !before.is_some_and(is_name_char) && !after.is_some_and(is_name_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replace_word, ShaderModules, MODULES, SHADERS_DIRECTORY};
+
+    #[test]
+    fn every_file_of_the_shaders_directory_is_a_module() {
+        let mut files: Vec<_> = std::fs::read_dir(SHADERS_DIRECTORY)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        let mut modules: Vec<_> = MODULES.iter().map(|(name, _)| name.to_string()).collect();
+        files.sort();
+        modules.sort();
+        assert_eq!(files, modules);
+    }
+
+    #[test]
+    fn shaders_are_put_together_like_wgsl_preprocessor_does() {
+        // it reads the included files from the working directory unless their path is absolute,
+        // so they're written somewhere else with absolute includes instead of changing the working directory
+        let directory =
+            std::env::temp_dir().join(format!("rt_shader_preprocessor_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (name, source) in MODULES {
+            let source: String = source
+                .lines()
+                .map(|line| match line.strip_prefix("//!include") {
+                    Some(includes) => includes
+                        .split_whitespace()
+                        .fold(String::from("//!include"), |line, include| {
+                            format!("{} {}", line, directory.join(include).display())
+                        }),
+                    None => line.to_string(),
+                })
+                .map(|line| line + "\n")
+                .collect();
+            std::fs::write(directory.join(name), source).unwrap();
+        }
+        for root in ["raytracing.wgsl", "render.wgsl"] {
+            let path = directory.join(root);
+            let builder = wgsl_preprocessor::ShaderBuilder::new(path.to_str().unwrap());
+            let (shader, definitions) = ShaderModules::embedded()
+                .load_module(root, &[], &mut Vec::new())
+                .unwrap();
+            assert!(!definitions.is_empty(), "{} has no defines", root);
+            assert_eq!(shader.code, builder.unwrap().source_string, "{}", root);
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn lines_keep_the_module_they_come_from() {
+        let modules = ShaderModules::embedded();
+        let shader = modules.shader_source("raytracing.wgsl", &[]).unwrap();
+        let line = shader
+            .code
+            .lines()
+            .position(|line| line.starts_with("fn hit_sphere("))
+            .unwrap();
+        let (_, shapes) = modules.module("shapes.wgsl").unwrap();
+        let line_in_shapes = shapes
+            .lines()
+            .position(|line| line.starts_with("fn hit_sphere("))
+            .unwrap();
+        assert_eq!(
+            shader.origin(line + 1),
+            Some(("shapes.wgsl", line_in_shapes + 1))
+        );
+        assert_eq!(shader.origin(shader.code.lines().count() + 1), None);
+    }
+
+    #[test]
+    fn defines_replace_the_value_of_the_modules() {
+        let modules = ShaderModules::embedded();
+        let default = modules.shader_source("render.wgsl", &[]).unwrap();
+        assert!(default.code.contains("if(true)"));
+        let chosen = modules
+            .shader_source("render.wgsl", &[("ENCODE_SRGB", "false".to_string())])
+            .unwrap();
+        assert!(chosen.code.contains("if(false)"));
+        assert!(!chosen.code.contains("ENCODE_SRGB"));
+    }
+
+    #[test]
+    fn defines_only_replace_whole_names() {
+        assert_eq!(
+            replace_word(
+                "if(ENCODE_SRGB && !ENCODE_SRGB_LATER) {",
+                "ENCODE_SRGB",
+                "true"
+            ),
+            "if(true && !ENCODE_SRGB_LATER) {"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::shader_modules::{
+    ComposedShader, ShaderError, ShaderLocation, MODULES, SHADERS_DIRECTORY,
+};
+
+/// how often the files of the shaders are checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/**
+ * Parses and validates a shader with naga like wgpu does when creating it,
+ * to know where the errors are instead of having wgpu panic on them
+ */
+pub fn check_shader(name: &str, shader: &ComposedShader) -> Result<(), ShaderError> {
+    // the path of the messages, their line numbers aren't the ones of the modules
+    let path = format!("{} with its modules", name);
+    let (location, message, details) = match naga::front::wgsl::parse_str(&shader.code) {
+        Err(error) => (
+            error.location(&shader.code),
+            error.to_string(),
+            error.emit_to_string_with_path(&shader.code, &path),
+        ),
+        Ok(module) => {
+            let validation = naga::valid::Validator::new(
+                naga::valid::ValidationFlags::all(),
+                naga::valid::Capabilities::empty(),
+            )
+            .validate(&module);
+            match validation {
+                Err(error) => (
+                    error.location(&shader.code),
+                    error.to_string(),
+                    error.emit_to_string_with_path(&shader.code, &path),
+                ),
+                Ok(_) => return Ok(()),
+            }
+        }
+    };
+    let location = location.and_then(|location| {
+        let (module, line) = shader.origin(location.line_number as usize)?;
+        Some(ShaderLocation {
+            module,
+            line,
+            column: location.line_position as usize,
+        })
+    });
+    Err(ShaderError {
+        location,
+        message: format!("{}: {}", name, message),
+        details,
+    })
+}
+
+/**
+ * Looks at when the files of the modules were last modified, polling them since there is
+ * no watcher crate around. It's only in native debug builds, which run where the sources are
+ */
+pub struct ShaderWatcher {
+    modified: Vec<Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        ShaderWatcher {
+            modified: modification_times(Path::new(SHADERS_DIRECTORY)),
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+impl ShaderWatcher {
+    /**
+     * True when a module was modified since the last time, they're only looked at every POLL_INTERVAL
+     */
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let modified = modification_times(Path::new(SHADERS_DIRECTORY));
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+/// none for the files that can't be read, reading them tells why
+fn modification_times(directory: &Path) -> Vec<Option<SystemTime>> {
+    MODULES
+        .iter()
+        .map(|(name, _)| {
+            std::fs::metadata(directory.join(name))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_shader;
+    use crate::gpu::compute_stage::compute_shader_source;
+    use crate::gpu::shader_modules::{ShaderLocation, ShaderModules, MODULES};
+    use crate::gpu::DEFAULT_WORKGROUP_SIZE;
+
+    fn module(name: &str) -> &'static str {
+        MODULES
+            .iter()
+            .find(|(module, _)| *module == name)
+            .unwrap()
+            .1
+    }
+
+    /**
+     * Reads the modules from a directory like a reload does, with some of them changed
+     */
+    fn reload(test: &str, changed: &[(&str, String)]) -> ShaderModules {
+        let directory =
+            std::env::temp_dir().join(format!("rt_shader_{}_{}", test, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (name, source) in MODULES {
+            std::fs::write(directory.join(name), source).unwrap();
+        }
+        for (name, source) in changed {
+            std::fs::write(directory.join(name), source).unwrap();
+        }
+        let modules = ShaderModules::read(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+        modules.unwrap()
+    }
+
+    /// the line and column of the first place the text is in a module, counted from 1
+    fn find(module: &'static str, source: &str, text: &str) -> ShaderLocation {
+        let (line, code) = source
+            .lines()
+            .enumerate()
+            .find(|(_, line)| line.contains(text))
+            .unwrap();
+        ShaderLocation {
+            module,
+            line: line + 1,
+            column: code.find(text).unwrap() + 1,
+        }
+    }
+
+    #[test]
+    fn errors_are_located_in_the_modules() {
+        // a typo in the sphere intersection
+        let shapes = module("shapes.wgsl").replacen("let half_b = dot(", "let half_b = dott(", 1);
+        let modules = reload("typo", &[("shapes.wgsl", shapes.clone())]);
+        let shader = compute_shader_source(&modules, DEFAULT_WORKGROUP_SIZE).unwrap();
+        let error = check_shader("raytracing.wgsl", &shader).unwrap_err();
+        assert_eq!(error.location, Some(find("shapes.wgsl", &shapes, "dott")));
+    }
+
+    #[test]
+    fn a_bad_include_is_an_error() {
+        let raytracing = module("raytracing.wgsl").replacen(" rng.wgsl ", " rgn.wgsl ", 1);
+        let modules = reload("include", &[("raytracing.wgsl", raytracing.clone())]);
+        let error = compute_shader_source(&modules, DEFAULT_WORKGROUP_SIZE).unwrap_err();
+        assert_eq!(
+            error.location,
+            Some(find("raytracing.wgsl", &raytracing, "rgn.wgsl"))
+        );
+        assert!(error.message.contains("rgn.wgsl"), "{}", error.message);
+    }
+
+    #[test]
+    fn an_include_cycle_is_an_error() {
+        let rng = format!("//!include sampling.wgsl\n{}", module("rng.wgsl"));
+        let sampling = format!("//!include rng.wgsl\n{}", module("sampling.wgsl"));
+        let modules = reload(
+            "cycle",
+            &[("rng.wgsl", rng), ("sampling.wgsl", sampling.clone())],
+        );
+        let error = compute_shader_source(&modules, DEFAULT_WORKGROUP_SIZE).unwrap_err();
+        assert_eq!(
+            error.location,
+            Some(find("sampling.wgsl", &sampling, "rng.wgsl"))
+        );
+    }
+
+    #[test]
+    fn a_missing_define_is_an_error() {
+        let raytracing =
+            module("raytracing.wgsl").replacen("//!define WORKGROUP_WIDTH 16\n", "", 1);
+        let modules = reload("define", &[("raytracing.wgsl", raytracing)]);
+        let error = compute_shader_source(&modules, DEFAULT_WORKGROUP_SIZE).unwrap_err();
+        assert_eq!(error.location, None);
+        assert!(
+            error.message.contains("WORKGROUP_WIDTH"),
+            "{}",
+            error.message
+        );
+    }
+}
@@ -1,54 +1,586 @@
+use std::sync::Arc;
+
 use eframe::wgpu::*;
 
-pub type SharedStageBindGroup = Vec<BindGroup>;
-pub type SharedStageBindGroupLayout = Vec<BindGroupLayout>;
+use crate::ray_tracer::bvh::BvhNode;
+use crate::ray_tracer::camera::Camera;
+use crate::ray_tracer::material::Material;
+use crate::ray_tracer::mesh::{Mesh, Triangle, Vertex};
+use crate::ray_tracer::scene::{Instance, Scene, Sphere};
+
+use super::bind_groups::{BindGroupBuilder, BindGroupSchema};
+use super::wgsl::{wgsl_struct, write_storage, WgslType};
+
+/// the index of a bvh node that isn't there, the NO_NODE of the shader
+pub const NO_NODE: u32 = u32::MAX;
+/// the material of an instance keeping the ones of its triangles, the NO_MATERIAL of the shader
+pub const NO_MATERIAL: u32 = u32::MAX;
 
+/**
+ * The scene in the gpu, shared by every viewport
+ */
 pub struct SharedStageData {
-    pub size_update_buffer: Buffer,
+    pub sphere_buffer: Buffer,
+    pub sphere_count: u32,
+    pub material_buffer: Buffer,
+    pub vertex_buffer: Buffer,
+    pub triangle_buffer: Buffer,
+    pub triangle_count: u32,
+    pub instance_buffer: Buffer,
+    /// the bvh of every mesh followed by the top level bvh
+    pub bvh_node_buffer: Buffer,
+    pub primitive_index_buffer: Buffer,
+    /// the index of the root of the top level bvh, NO_NODE when the scene is empty
+    pub tlas_root: u32,
+    /// the meshes currently in the buffers, they are only uploaded again when they change
+    uploaded_meshes: Vec<Arc<Mesh>>,
+    /// where the bvh of each uploaded mesh starts, NO_NODE for meshes without triangles
+    blas_roots: Vec<u32>,
+    blas_node_count: u32,
+    blas_primitive_count: u32,
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct SharedStageUniform {
-    pub size: [f32; 2],
+wgsl_struct! {
+    pub struct SharedStageUniform as SharedStageUniform {
+        pub size: [f32; 2],
+        pub sphere_count: u32,
+        /// u32::MAX when there is nothing in the scene
+        pub tlas_root: u32,
+        /// the maximum number of times a ray bounces
+        pub max_depth: u32,
+        /// the number of samples already in previous_accumulation, it's also the index of the frame
+        pub sample_count: u32,
+        pub seed: u32,
+        pub _padding: u32,
+    }
 }
 
-pub fn get_shared_data(device: &Device) -> SharedStageData {
-    let size_update_buffer = device.create_buffer(&BufferDescriptor {
+wgsl_struct! {
+    /**
+     * The camera with its axes already computed, the shader only has to place the pixels on the viewport
+     */
+    pub struct GpuCamera as Camera {
+        pub position: [f32; 3],
+        /// half the height of the viewport at a distance of 1
+        pub viewport_half_height: f32,
+        pub right: [f32; 3],
+        /// 0 for a pinhole camera where everything is in focus
+        pub lens_radius: f32,
+        pub up: [f32; 3],
+        pub focus_distance: f32,
+        pub forward: [f32; 3],
+        pub _padding3: f32,
+    }
+}
+
+impl From<&Camera> for GpuCamera {
+    fn from(camera: &Camera) -> Self {
+        let forward = camera.forward();
+        let right = camera.right();
+        GpuCamera {
+            position: camera.position.into(),
+            viewport_half_height: (camera.vertical_fov.to_radians() / 2.0).tan(),
+            right: right.into(),
+            lens_radius: camera.aperture / 2.0,
+            up: right.cross(forward).into(),
+            focus_distance: camera.focus_distance,
+            forward: forward.into(),
+            _padding3: 0.0,
+        }
+    }
+}
+
+wgsl_struct! {
+    pub struct GpuSphere as Sphere {
+        pub center: [f32; 3],
+        pub radius: f32,
+        pub material: u32,
+        // a vec3u would be aligned on 16 bytes
+        pub _padding0: u32,
+        pub _padding1: u32,
+        pub _padding2: u32,
+    }
+}
+
+impl From<&Sphere> for GpuSphere {
+    fn from(sphere: &Sphere) -> Self {
+        GpuSphere {
+            center: [sphere.center.x, sphere.center.y, sphere.center.z],
+            radius: sphere.radius,
+            material: sphere.material,
+            _padding0: 0,
+            _padding1: 0,
+            _padding2: 0,
+        }
+    }
+}
+
+/// the kind of a material, the same as the constants of the shader
+pub const LAMBERTIAN: u32 = 0;
+pub const METAL: u32 = 1;
+pub const DIELECTRIC: u32 = 2;
+pub const EMISSIVE: u32 = 3;
+
+wgsl_struct! {
+    /**
+     * Every kind of material in the same struct, the kind says how to read the other fields
+     */
+    pub struct GpuMaterial as Material {
+        pub albedo: [f32; 3],
+        pub kind: u32,
+        /// the light given off, already multiplied by the strength
+        pub emission: [f32; 3],
+        /// the fuzz of a metal or the refraction index of a dielectric
+        pub parameter: f32,
+    }
+}
+
+impl From<&Material> for GpuMaterial {
+    fn from(material: &Material) -> Self {
+        let (kind, albedo, emission, parameter) = match *material {
+            Material::Lambertian { albedo } => (LAMBERTIAN, albedo.into(), [0.0; 3], 0.0),
+            Material::Metal { albedo, fuzz } => (METAL, albedo.into(), [0.0; 3], fuzz),
+            Material::Dielectric { refraction_index } => {
+                (DIELECTRIC, [1.0; 3], [0.0; 3], refraction_index)
+            }
+            Material::Emissive { color, strength } => {
+                (EMISSIVE, [0.0; 3], (color * strength).into(), 0.0)
+            }
+        };
+        GpuMaterial {
+            albedo,
+            kind,
+            emission,
+            parameter,
+        }
+    }
+}
+
+// the uv is split in two to fill the padding after each vec3
+wgsl_struct! {
+    pub struct GpuVertex as Vertex {
+        pub position: [f32; 3],
+        pub u: f32,
+        pub normal: [f32; 3],
+        pub v: f32,
+    }
+}
+
+impl From<&Vertex> for GpuVertex {
+    fn from(vertex: &Vertex) -> Self {
+        GpuVertex {
+            position: vertex.position.into(),
+            u: vertex.uv[0],
+            normal: vertex.normal.into(),
+            v: vertex.uv[1],
+        }
+    }
+}
+
+wgsl_struct! {
+    pub struct GpuTriangle as Triangle {
+        pub indices: [u32; 3],
+        pub material: u32,
+    }
+}
+
+impl GpuTriangle {
+    /**
+     * the indices of a triangle are relative to its mesh but all the meshes share the same vertex buffer
+     */
+    fn new(triangle: &Triangle, first_vertex: u32) -> Self {
+        GpuTriangle {
+            indices: triangle.indices.map(|index| index + first_vertex),
+            material: triangle.material,
+        }
+    }
+}
+
+wgsl_struct! {
+    pub struct GpuInstance as Instance {
+        /// the rays are moved in the space of the mesh instead of moving the mesh
+        pub world_to_object: [[f32; 4]; 4],
+        /// the root of the bvh of the mesh, NO_NODE when the mesh has no triangles
+        pub blas_root: u32,
+        /// NO_MATERIAL to keep the materials of the triangles
+        pub material: u32,
+        pub _padding: [u32; 2],
+    }
+}
+
+impl GpuInstance {
+    fn new(instance: &Instance, blas_root: u32) -> Self {
+        GpuInstance {
+            world_to_object: instance.transform.inverse_matrix().columns,
+            blas_root,
+            material: instance.material.unwrap_or(NO_MATERIAL),
+            _padding: [0; 2],
+        }
+    }
+}
+
+wgsl_struct! {
+    pub struct GpuBvhNode as BvhNode {
+        pub min: [f32; 3],
+        /// the left child, the right one is next to it, or the first primitive for a leaf
+        pub left_or_first: u32,
+        pub max: [f32; 3],
+        /// 0 when it's not a leaf
+        pub primitive_count: u32,
+    }
+}
+
+impl GpuBvhNode {
+    /**
+     * All the trees share the same buffers, so the children and primitives
+     * are moved by where the tree starts in them
+     */
+    fn new(node: &BvhNode, node_offset: u32, primitive_offset: u32) -> Self {
+        let offset = if node.primitive_count == 0 {
+            node_offset
+        } else {
+            primitive_offset
+        };
+        GpuBvhNode {
+            min: node.bounds.min.into(),
+            left_or_first: node.left_or_first + offset,
+            max: node.bounds.max.into(),
+            primitive_count: node.primitive_count,
+        }
+    }
+}
+
+/**
+ * The uniform of a viewport, every viewport has its own since they are all written before the frame is submitted
+ */
+pub fn get_uniform_buffer(device: &Device) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
         label: Some("The buffer containing information for the fragment stange"),
         size: std::mem::size_of::<SharedStageUniform>() as u64,
         mapped_at_creation: false,
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-    });
-    SharedStageData { size_update_buffer }
+    })
+}
+
+pub fn get_camera_buffer(device: &Device) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("The buffer containing the camera"),
+        size: std::mem::size_of::<GpuCamera>() as u64,
+        mapped_at_creation: false,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    })
+}
+
+pub fn get_shared_data(device: &Device) -> SharedStageData {
+    SharedStageData {
+        sphere_buffer: create_storage_buffer::<GpuSphere>(device, SPHERE_LABEL, 1),
+        sphere_count: 0,
+        material_buffer: create_storage_buffer::<GpuMaterial>(device, MATERIAL_LABEL, 1),
+        vertex_buffer: create_storage_buffer::<GpuVertex>(device, VERTEX_LABEL, 1),
+        triangle_buffer: create_storage_buffer::<GpuTriangle>(device, TRIANGLE_LABEL, 1),
+        triangle_count: 0,
+        instance_buffer: create_storage_buffer::<GpuInstance>(device, INSTANCE_LABEL, 1),
+        bvh_node_buffer: create_storage_buffer::<GpuBvhNode>(device, BVH_NODE_LABEL, 1),
+        primitive_index_buffer: create_storage_buffer::<u32>(device, PRIMITIVE_INDEX_LABEL, 1),
+        tlas_root: NO_NODE,
+        uploaded_meshes: Vec::new(),
+        blas_roots: Vec::new(),
+        blas_node_count: 0,
+        blas_primitive_count: 0,
+    }
+}
+
+const SPHERE_LABEL: &str = "Storage buffer with the spheres of the scene";
+const MATERIAL_LABEL: &str = "Storage buffer with the materials of the scene";
+const VERTEX_LABEL: &str = "Storage buffer with the vertices of the meshes";
+const TRIANGLE_LABEL: &str = "Storage buffer with the triangles of the meshes";
+const INSTANCE_LABEL: &str = "Storage buffer with the instances of the meshes";
+const BVH_NODE_LABEL: &str = "Storage buffer with the nodes of the bvh";
+const PRIMITIVE_INDEX_LABEL: &str = "Storage buffer with the primitives of the bvh leaves";
+
+/**
+ * a storage buffer can't be empty so the capacity is always at least one element
+ */
+fn create_storage_buffer<T>(device: &Device, label: &str, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: (capacity.max(1) * std::mem::size_of::<T>()) as u64,
+        mapped_at_creation: false,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    })
+}
+
+/**
+ * Replaces the buffer with a bigger one when the elements don't fit.
+ * Returns true if the buffer was replaced, which loses its content
+ */
+fn reserve_storage_buffer<T>(
+    device: &Device,
+    buffer: &mut Buffer,
+    label: &str,
+    length: usize,
+) -> bool {
+    let recreated = (length * std::mem::size_of::<T>()) as u64 > buffer.size();
+    if recreated {
+        // double the size so that adding elements one by one doesn't recreate the buffer every time
+        let capacity = length.next_power_of_two();
+        *buffer = create_storage_buffer::<T>(device, label, capacity);
+    }
+    recreated
+}
+
+/**
+ * Writes the elements to the storage buffer, replacing the buffer with a bigger one when they don't fit.
+ * Returns true if the buffer was replaced
+ */
+fn write_storage_buffer<T: WgslType + bytemuck::Pod>(
+    device: &Device,
+    queue: &Queue,
+    buffer: &mut Buffer,
+    label: &str,
+    elements: &[T],
+) -> bool {
+    let recreated = reserve_storage_buffer::<T>(device, buffer, label, elements.len());
+    write_storage(queue, buffer, 0, elements);
+    recreated
+}
+
+/**
+ * Writes the scene to the storage buffers.
+ * The meshes and their bvh are only written when the list of meshes changed, so moving
+ * things around only uploads the spheres, the instances and the top level bvh.
+ * If a buffer is too small, a bigger one is created and this returns true
+ * since the bind groups pointing to the old buffer have to be recreated
+ */
+pub fn write_scene(
+    device: &Device,
+    queue: &Queue,
+    shared_stage_data: &mut SharedStageData,
+    scene: &Scene,
+) -> bool {
+    let spheres: Vec<GpuSphere> = scene.spheres.iter().map(GpuSphere::from).collect();
+    let mut recreated = write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.sphere_buffer,
+        SPHERE_LABEL,
+        &spheres,
+    );
+    let materials: Vec<GpuMaterial> = scene.materials.iter().map(GpuMaterial::from).collect();
+    recreated |= write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.material_buffer,
+        MATERIAL_LABEL,
+        &materials,
+    );
+
+    let meshes_changed = shared_stage_data.uploaded_meshes.len() != scene.meshes.len()
+        || shared_stage_data
+            .uploaded_meshes
+            .iter()
+            .zip(&scene.meshes)
+            .any(|(uploaded, mesh)| !Arc::ptr_eq(uploaded, mesh));
+    if meshes_changed {
+        recreated |= write_meshes(device, queue, shared_stage_data, &scene.meshes);
+    }
+
+    let instances: Vec<GpuInstance> = scene
+        .instances
+        .iter()
+        .map(|instance| {
+            GpuInstance::new(
+                instance,
+                shared_stage_data.blas_roots[instance.mesh as usize],
+            )
+        })
+        .collect();
+    recreated |= write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.instance_buffer,
+        INSTANCE_LABEL,
+        &instances,
+    );
+
+    // the top level bvh goes after the bvh of the meshes
+    let node_offset = shared_stage_data.blas_node_count;
+    let primitive_offset = shared_stage_data.blas_primitive_count;
+    let tlas_nodes: Vec<GpuBvhNode> = scene
+        .bvh
+        .nodes
+        .iter()
+        .map(|node| GpuBvhNode::new(node, node_offset, primitive_offset))
+        .collect();
+    let nodes_recreated = reserve_storage_buffer::<GpuBvhNode>(
+        device,
+        &mut shared_stage_data.bvh_node_buffer,
+        BVH_NODE_LABEL,
+        node_offset as usize + tlas_nodes.len(),
+    );
+    let primitives_recreated = reserve_storage_buffer::<u32>(
+        device,
+        &mut shared_stage_data.primitive_index_buffer,
+        PRIMITIVE_INDEX_LABEL,
+        primitive_offset as usize + scene.bvh.primitive_indices.len(),
+    );
+    if nodes_recreated || primitives_recreated {
+        // the bvh of the meshes was in the old buffers
+        write_meshes(device, queue, shared_stage_data, &scene.meshes);
+        recreated = true;
+    }
+    write_storage(
+        queue,
+        &shared_stage_data.bvh_node_buffer,
+        node_offset as usize,
+        &tlas_nodes,
+    );
+    write_storage(
+        queue,
+        &shared_stage_data.primitive_index_buffer,
+        primitive_offset as usize,
+        &scene.bvh.primitive_indices,
+    );
+
+    shared_stage_data.sphere_count = spheres.len() as u32;
+    shared_stage_data.tlas_root = if tlas_nodes.is_empty() {
+        NO_NODE
+    } else {
+        node_offset
+    };
+    recreated
+}
+
+/**
+ * Writes the vertices, the triangles and the bvh of every mesh at the start of the buffers.
+ * Returns true if a buffer was replaced
+ */
+fn write_meshes(
+    device: &Device,
+    queue: &Queue,
+    shared_stage_data: &mut SharedStageData,
+    meshes: &[Arc<Mesh>],
+) -> bool {
+    let mut vertices: Vec<GpuVertex> = Vec::new();
+    let mut triangles: Vec<GpuTriangle> = Vec::new();
+    let mut nodes: Vec<GpuBvhNode> = Vec::new();
+    let mut primitive_indices: Vec<u32> = Vec::new();
+    let mut blas_roots = Vec::with_capacity(meshes.len());
+    for mesh in meshes {
+        let first_vertex = vertices.len() as u32;
+        let first_triangle = triangles.len() as u32;
+        let node_offset = nodes.len() as u32;
+        let primitive_offset = primitive_indices.len() as u32;
+        blas_roots.push(if mesh.bvh.nodes.is_empty() {
+            NO_NODE
+        } else {
+            node_offset
+        });
+
+        vertices.extend(mesh.vertices.iter().map(GpuVertex::from));
+        triangles.extend(
+            mesh.triangles
+                .iter()
+                .map(|triangle| GpuTriangle::new(triangle, first_vertex)),
+        );
+        nodes.extend(
+            mesh.bvh
+                .nodes
+                .iter()
+                .map(|node| GpuBvhNode::new(node, node_offset, primitive_offset)),
+        );
+        primitive_indices.extend(
+            mesh.bvh
+                .primitive_indices
+                .iter()
+                .map(|&triangle| triangle + first_triangle),
+        );
+    }
+
+    let mut recreated = write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.vertex_buffer,
+        VERTEX_LABEL,
+        &vertices,
+    );
+    recreated |= write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.triangle_buffer,
+        TRIANGLE_LABEL,
+        &triangles,
+    );
+    recreated |= write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.bvh_node_buffer,
+        BVH_NODE_LABEL,
+        &nodes,
+    );
+    recreated |= write_storage_buffer(
+        device,
+        queue,
+        &mut shared_stage_data.primitive_index_buffer,
+        PRIMITIVE_INDEX_LABEL,
+        &primitive_indices,
+    );
+
+    shared_stage_data.triangle_count = triangles.len() as u32;
+    shared_stage_data.blas_node_count = nodes.len() as u32;
+    shared_stage_data.blas_primitive_count = primitive_indices.len() as u32;
+    shared_stage_data.blas_roots = blas_roots;
+    shared_stage_data.uploaded_meshes = meshes.to_vec();
+    recreated
+}
+
+/**
+ * The scene with the uniforms of a viewport, the uniform is the only thing the render stage uses
+ */
+pub fn get_shared_stage_bind_group_schema(device: &Device) -> BindGroupSchema {
+    BindGroupBuilder::new("Shared stage bind group")
+        .uniform::<SharedStageUniform>(ShaderStages::COMPUTE | ShaderStages::VERTEX_FRAGMENT)
+        // the spheres of the scene
+        .storage::<GpuSphere>(ShaderStages::COMPUTE)
+        // the vertices of the meshes
+        .storage::<GpuVertex>(ShaderStages::COMPUTE)
+        // the triangles of the meshes
+        .storage::<GpuTriangle>(ShaderStages::COMPUTE)
+        // the instances of the meshes
+        .storage::<GpuInstance>(ShaderStages::COMPUTE)
+        // the nodes of the bvh of every mesh and of the top level bvh
+        .storage::<GpuBvhNode>(ShaderStages::COMPUTE)
+        // the primitives in the leaves of the bvh
+        .storage::<u32>(ShaderStages::COMPUTE)
+        // the materials of the scene
+        .storage::<GpuMaterial>(ShaderStages::COMPUTE)
+        .uniform::<GpuCamera>(ShaderStages::COMPUTE)
+        .build(device)
 }
 
-pub fn get_shared_stage_bind_group(
+/**
+ * Creates the bind group of a viewport with the scene and its own uniforms,
+ * it's created again when a buffer of the shared data is recreated
+ */
+pub fn create_shared_stage_bind_group(
     device: &Device,
+    schema: &BindGroupSchema,
     shared_stage_data: &SharedStageData,
-) -> (SharedStageBindGroupLayout, SharedStageBindGroup) {
-    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("Shared stage bind group layout"),
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::COMPUTE | ShaderStages::VERTEX_FRAGMENT,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
-    });
-
-    let group = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("Shared stage bind group"),
-        layout: &layout,
-        entries: &[BindGroupEntry {
-            binding: 0,
-            resource: shared_stage_data.size_update_buffer.as_entire_binding(),
-        }],
-    });
-
-    (vec![layout], vec![group])
+    uniform_buffer: &Buffer,
+    camera_buffer: &Buffer,
+) -> BindGroup {
+    schema.create_bind_group(
+        device,
+        &[
+            uniform_buffer.as_entire_binding(),
+            shared_stage_data.sphere_buffer.as_entire_binding(),
+            shared_stage_data.vertex_buffer.as_entire_binding(),
+            shared_stage_data.triangle_buffer.as_entire_binding(),
+            shared_stage_data.instance_buffer.as_entire_binding(),
+            shared_stage_data.bvh_node_buffer.as_entire_binding(),
+            shared_stage_data.primitive_index_buffer.as_entire_binding(),
+            shared_stage_data.material_buffer.as_entire_binding(),
+            camera_buffer.as_entire_binding(),
+        ],
+    )
 }
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use eframe::egui_wgpu::wgpu::*;
+
+/// the number of buffers the timestamps are copied to, a buffer is only reused once it's been read
+const READBACK_COUNT: usize = 4;
+/// the size of the two timestamps
+const TIMESTAMPS_SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+/// the number of frames kept in the history
+pub const HISTORY_LENGTH: usize = 240;
+
+/// what the callback of map_async tells about a buffer being mapped
+const MAP_PENDING: u8 = 0;
+const MAP_OK: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+enum ReadbackState {
+    /// can receive the timestamps of a frame
+    Free,
+    /// the timestamps were copied in a command buffer that isn't submitted yet, so it can't be mapped
+    Copied,
+    /// waiting for the gpu until the callback sets MAP_OK or MAP_FAILED
+    Mapping(Arc<AtomicU8>),
+}
+
+struct Readback {
+    buffer: Buffer,
+    state: ReadbackState,
+}
+
+/**
+ * Times the compute pass with timestamps without waiting for the gpu.
+ * The timestamps of a frame are copied to one of a few buffers which is read a few frames later
+ */
+pub struct TimeQuery {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readbacks: Vec<Readback>,
+}
+
+impl TimeQuery {
+    pub fn new(device: &Device) -> Self {
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("Query set for a time stamp"),
+            count: 2,
+            ty: QueryType::Timestamp,
+        });
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Buffer for querying the time"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACK_COUNT)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&BufferDescriptor {
+                    label: Some("Buffer for copying and reading the time information"),
+                    size: TIMESTAMPS_SIZE,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                state: ReadbackState::Free,
+            })
+            .collect();
+        TimeQuery {
+            query_set,
+            resolve_buffer,
+            readbacks,
+        }
+    }
+
+    pub fn write_start(&self, encoder: &mut CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 0);
+    }
+
+    pub fn write_end(&self, encoder: &mut CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+    }
+
+    /**
+     * Copies the timestamps written this frame to a free buffer.
+     * When every buffer is still waiting for the gpu the timestamps of this frame are dropped
+     */
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        let Some(readback) = self
+            .readbacks
+            .iter_mut()
+            .find(|readback| matches!(readback.state, ReadbackState::Free))
+        else {
+            return;
+        };
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            TIMESTAMPS_SIZE,
+        );
+        readback.state = ReadbackState::Copied;
+    }
+
+    /**
+     * Starts mapping the buffers copied in the previous frames and gives the times
+     * in milliseconds of the ones that are ready, without blocking
+     */
+    pub fn harvest(&mut self, device: &Device, queue: &Queue) -> Vec<f64> {
+        let period = queue.get_timestamp_period() as f64;
+        let mut times = Vec::new();
+        for readback in &mut self.readbacks {
+            match &readback.state {
+                ReadbackState::Free => {}
+                ReadbackState::Copied => {
+                    let mapped = Arc::new(AtomicU8::new(MAP_PENDING));
+                    let callback_mapped = mapped.clone();
+                    readback
+                        .buffer
+                        .slice(..)
+                        .map_async(MapMode::Read, move |result| {
+                            let state = if result.is_ok() { MAP_OK } else { MAP_FAILED };
+                            callback_mapped.store(state, Ordering::Release);
+                        });
+                    readback.state = ReadbackState::Mapping(mapped);
+                }
+                ReadbackState::Mapping(mapped) => {
+                    match mapped.load(Ordering::Acquire) {
+                        MAP_PENDING => continue,
+                        MAP_FAILED => {
+                            // the timestamps of that frame are lost but the buffer can take the next ones
+                            readback.state = ReadbackState::Free;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    {
+                        let view = readback.buffer.slice(..).get_mapped_range();
+                        let timestamps: &[u64] = bytemuck::cast_slice(&view);
+                        let ticks = timestamps[1].wrapping_sub(timestamps[0]);
+                        times.push(ticks as f64 * period * 1e-6);
+                    } // have to drop the view into the buffer before unmapping
+                    readback.buffer.unmap();
+                    readback.state = ReadbackState::Free;
+                }
+            }
+        }
+        // runs the callbacks of the buffers that finished mapping
+        device.poll(Maintain::Poll);
+        times
+    }
+}
+
+/**
+ * Where the render times come from, timestamp queries aren't supported by every adapter
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingSource {
+    /// the time the compute pass takes on the gpu
+    GpuTimestamps,
+    /// the time between two frames on the cpu, which includes everything else done in the frame
+    #[default]
+    CpuFrameTime,
+}
+
+impl TimingSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimingSource::GpuTimestamps => "gpu timestamps",
+            TimingSource::CpuFrameTime => "cpu frame time",
+        }
+    }
+}
+
+/**
+ * The last few render times, in milliseconds
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TimeHistory {
+    pub times: VecDeque<f64>,
+    pub source: TimingSource,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimeStatistics {
+    pub min: f64,
+    pub average: f64,
+    pub max: f64,
+    /// 95% of the frames are faster than this
+    pub p95: f64,
+}
+
+impl TimeHistory {
+    pub fn push(&mut self, time: f64) {
+        if self.times.len() == HISTORY_LENGTH {
+            self.times.pop_front();
+        }
+        self.times.push_back(time);
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.times.back().copied()
+    }
+
+    pub fn statistics(&self) -> Option<TimeStatistics> {
+        if self.times.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.times.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let p95_index = ((sorted.len() as f64 * 0.95).ceil() as usize).max(1) - 1;
+        Some(TimeStatistics {
+            min: sorted[0],
+            average: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            max: sorted[sorted.len() - 1],
+            p95: sorted[p95_index],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeHistory, HISTORY_LENGTH};
+
+    fn history(times: impl IntoIterator<Item = f64>) -> TimeHistory {
+        let mut history = TimeHistory::default();
+        for time in times {
+            history.push(time);
+        }
+        history
+    }
+
+    #[test]
+    fn the_p95_is_the_time_95_percent_of_the_frames_are_faster_than() {
+        // pushed out of order, 1 to 100
+        let statistics = history((1..=100).rev().map(f64::from))
+            .statistics()
+            .unwrap();
+        assert_eq!(statistics.min, 1.0);
+        assert_eq!(statistics.max, 100.0);
+        assert_eq!(statistics.average, 50.5);
+        assert_eq!(statistics.p95, 95.0);
+        // 95% of 10 frames is 9.5, so only the slowest one is above it
+        let statistics = history((1..=10).map(f64::from)).statistics().unwrap();
+        assert_eq!(statistics.p95, 10.0);
+    }
+
+    #[test]
+    fn a_single_time_is_every_statistic() {
+        assert!(TimeHistory::default().statistics().is_none());
+        let statistics = history([4.0]).statistics().unwrap();
+        assert_eq!(
+            [
+                statistics.min,
+                statistics.average,
+                statistics.max,
+                statistics.p95
+            ],
+            [4.0; 4]
+        );
+    }
+
+    #[test]
+    fn the_oldest_times_are_dropped_past_the_history_length() {
+        let history = history((0..HISTORY_LENGTH + 10).map(|time| time as f64));
+        assert_eq!(history.times.len(), HISTORY_LENGTH);
+        assert_eq!(history.times.front(), Some(&10.0));
+        assert_eq!(history.last(), Some((HISTORY_LENGTH + 9) as f64));
+        assert_eq!(history.statistics().unwrap().min, 10.0);
+    }
+}
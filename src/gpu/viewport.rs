@@ -0,0 +1,293 @@
+use eframe::egui_wgpu::wgpu::*;
+use web_time::Instant;
+
+use crate::ray_tracer::camera::Camera;
+use crate::ray_tracer::scene::Scene;
+
+use super::compute_stage::{
+    create_compute_bind_groups, get_accumulation_textures, ComputeBindGroups,
+};
+use super::render_stage::{
+    create_render_bind_group, get_display_buffer, get_output_texture, DisplaySettings,
+    DisplayUniform,
+};
+use super::renderer::{
+    random_seed, texture_size, BindGroupSlot, PipelineBindings, SharedResources, TEXTURE_SIZE_STEP,
+};
+use super::shared_stage_data::{
+    create_shared_stage_bind_group, get_camera_buffer, get_uniform_buffer, GpuCamera,
+    SharedStageUniform,
+};
+use super::time_query::TimeQuery;
+use super::wgsl::write_uniform;
+use super::DEFAULT_WORKGROUP_SIZE;
+
+/**
+ * What every viewport has for itself, its image with the uniforms describing it.
+ * The uniforms can't be shared since the ones of every viewport are written before the frame is submitted
+ */
+pub struct ViewportResources {
+    /// the size, sample count and seed of the image
+    pub uniform_buffer: Buffer,
+    pub camera_buffer: Buffer,
+    pub display_buffer: Buffer,
+    /// the display settings in the buffer
+    pub display: Option<DisplaySettings>,
+    /// the compute pipeline with this workgroup size is used for this viewport
+    pub workgroup_size: [u32; 2],
+    pub render_bind_group: BindGroup,
+    pub compute_bind_groups: ComputeBindGroups,
+    /// the scene with the uniforms of this viewport
+    pub shared_stage_bind_group: BindGroup,
+    /// the sums of the samples, kept to copy the image back in high dynamic range
+    pub accumulation_textures: [Texture; 2],
+    /// the size of the output and accumulation textures, which can be bigger than the image
+    pub texture_size: [u32; 2],
+    /// the revision of the scene in the accumulation texture
+    pub scene_revision: Option<u64>,
+    /// the number of samples added up in the accumulation texture
+    pub sample_count: u32,
+    /// the size of the image and the camera used for the accumulation texture
+    pub accumulated_size: [u32; 2],
+    pub accumulated_camera: Option<Camera>,
+    /// the seed of the random numbers of the image in the accumulation texture
+    pub seed: u32,
+    /// false once the samples target is reached, there is nothing to compute or time then
+    pub rendering: bool,
+    /// the render times are measured on the cpu without the timestamp query feature
+    pub time_query: Option<TimeQuery>,
+    /// when the previous frame was rendered, only set while rendering so idle time isn't counted
+    pub last_frame: Option<Instant>,
+}
+
+impl ViewportResources {
+    pub fn new(device: &Device, shared: &SharedResources) -> Self {
+        let uniform_buffer = get_uniform_buffer(device);
+        let camera_buffer = get_camera_buffer(device);
+        let display_buffer = get_display_buffer(device);
+        // the textures are resized to the viewport before the first frame
+        let texture_size = [TEXTURE_SIZE_STEP; 2];
+        let (accumulation_textures, render_bind_group, compute_bind_groups) =
+            create_textures(device, shared, &display_buffer, texture_size);
+        let shared_stage_bind_group = create_shared_stage_bind_group(
+            device,
+            &shared.layouts.shared_stage,
+            &shared.shared_stage_data,
+            &uniform_buffer,
+            &camera_buffer,
+        );
+        ViewportResources {
+            uniform_buffer,
+            camera_buffer,
+            display_buffer,
+            display: None,
+            workgroup_size: DEFAULT_WORKGROUP_SIZE,
+            render_bind_group,
+            compute_bind_groups,
+            shared_stage_bind_group,
+            accumulation_textures,
+            texture_size,
+            scene_revision: None,
+            sample_count: 0,
+            accumulated_size: [0; 2],
+            accumulated_camera: None,
+            seed: 0,
+            rendering: false,
+            // the feature is only there if the adapter had it when the device was requested
+            time_query: device
+                .features()
+                .contains(Features::TIMESTAMP_QUERY)
+                .then(|| TimeQuery::new(device)),
+            last_frame: None,
+        }
+    }
+
+    /**
+     * The accumulation texture the last sample was added to, the other one is a sample behind
+     */
+    pub fn latest_accumulation(&self) -> &Texture {
+        // sample n reads from texture n % 2 and writes to the other one
+        &self.accumulation_textures[self.sample_count as usize % 2]
+    }
+
+    /**
+     * Resizes the output and accumulation textures when the image doesn't fit them anymore.
+     * The accumulation is lost, but it's always reset when the size of the image changes
+     */
+    pub fn fit_textures(
+        &mut self,
+        device: &Device,
+        shared: &SharedResources,
+        image_size: [u32; 2],
+    ) {
+        let max_texture_dimension = device.limits().max_texture_dimension_2d;
+        let texture_size = texture_size(self.texture_size, image_size, max_texture_dimension);
+        if texture_size == self.texture_size {
+            return;
+        }
+        (
+            self.accumulation_textures,
+            self.render_bind_group,
+            self.compute_bind_groups,
+        ) = create_textures(device, shared, &self.display_buffer, texture_size);
+        self.texture_size = texture_size;
+    }
+
+    /**
+     * Used when the buffers of the scene are recreated
+     */
+    pub fn recreate_shared_stage_bind_group(&mut self, device: &Device, shared: &SharedResources) {
+        self.shared_stage_bind_group = create_shared_stage_bind_group(
+            device,
+            &shared.layouts.shared_stage,
+            &shared.shared_stage_data,
+            &self.uniform_buffer,
+            &self.camera_buffer,
+        );
+    }
+
+    /**
+     * The next frame starts a new image, when the samples so far were made with other shaders
+     */
+    pub fn restart_image(&mut self) {
+        self.accumulated_camera = None;
+    }
+
+    pub fn set_display(&mut self, queue: &Queue, display: &DisplaySettings) {
+        if self.display.as_ref() == Some(display) {
+            return;
+        }
+        write_uniform(queue, &self.display_buffer, &DisplayUniform::from(display));
+        self.display = Some(*display);
+    }
+
+    /**
+     * Starts a new image when the scene, the size of the image or the camera changed,
+     * the samples of the previous image would be mixed with the new one
+     */
+    pub fn start_image(
+        &mut self,
+        queue: &Queue,
+        scene: &Scene,
+        scene_revision: u64,
+        camera: &Camera,
+        image_size: [u32; 2],
+    ) {
+        if self.scene_revision == Some(scene_revision)
+            && self.accumulated_size == image_size
+            && self.accumulated_camera.as_ref() == Some(camera)
+        {
+            return;
+        }
+        self.sample_count = 0;
+        self.scene_revision = Some(scene_revision);
+        self.accumulated_size = image_size;
+        self.accumulated_camera = Some(*camera);
+        write_uniform(queue, &self.camera_buffer, &GpuCamera::from(camera));
+        self.seed = scene.settings.seed.unwrap_or_else(random_seed);
+    }
+
+    /**
+     * Adds one sample to every pixel of the image.
+     * The uniform is written with the queue, so the encoder has to be submitted before the next sample
+     */
+    pub fn render_sample(
+        &mut self,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        shared: &SharedResources,
+        max_depth: u32,
+    ) {
+        let image_size = self.accumulated_size;
+        if let Some(time_query) = &self.time_query {
+            // write the query before computing
+            time_query.write_start(encoder);
+        }
+        write_uniform(
+            queue,
+            &self.uniform_buffer,
+            &SharedStageUniform {
+                size: [image_size[0] as f32, image_size[1] as f32],
+                sphere_count: shared.shared_stage_data.sphere_count,
+                tlas_root: shared.shared_stage_data.tlas_root,
+                max_depth,
+                sample_count: self.sample_count,
+                seed: self.seed,
+                _padding: 0,
+            },
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Compute pass"),
+            });
+            compute_pass.set_pipeline(&shared.compute_pipelines[&self.workgroup_size]);
+            PipelineBindings::COMPUTE.set_bind_groups(&mut compute_pass, self);
+
+            // rounded up so the edges are covered, the shader skips the pixels outside of the image
+            let width = image_size[0].div_ceil(self.workgroup_size[0]);
+            let height = image_size[1].div_ceil(self.workgroup_size[1]);
+            compute_pass.dispatch_workgroups(width, height, 1);
+        }
+
+        if let Some(time_query) = &mut self.time_query {
+            time_query.write_end(encoder);
+            time_query.resolve(encoder);
+        }
+
+        self.sample_count += 1;
+    }
+
+    /**
+     * The bind group of this viewport a pipeline uses in a slot
+     */
+    pub fn bind_group(&self, slot: BindGroupSlot) -> &BindGroup {
+        match slot {
+            // the accumulation textures swap places every frame
+            BindGroupSlot::Compute => &self.compute_bind_groups[self.sample_count as usize % 2],
+            BindGroupSlot::Render => &self.render_bind_group,
+            BindGroupSlot::SharedStage => &self.shared_stage_bind_group,
+        }
+    }
+
+    /**
+     * Paints the image on the whole target of the render pass
+     */
+    pub fn draw<'rp>(&'rp self, render_pass: &mut RenderPass<'rp>, pipeline: &'rp RenderPipeline) {
+        render_pass.set_pipeline(pipeline);
+        PipelineBindings::RENDER.set_bind_groups(render_pass, self);
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+/**
+ * The output and accumulation textures with the bind groups pointing to them
+ */
+fn create_textures(
+    device: &Device,
+    shared: &SharedResources,
+    display_buffer: &Buffer,
+    texture_size: [u32; 2],
+) -> ([Texture; 2], BindGroup, ComputeBindGroups) {
+    // the view keeps the texture alive
+    let (_, texture_view) = get_output_texture(device, texture_size);
+    let (accumulation_textures, accumulation_views) =
+        get_accumulation_textures(device, texture_size);
+    let compute_bind_groups = create_compute_bind_groups(
+        device,
+        &shared.layouts.compute,
+        &texture_view,
+        &accumulation_views,
+    );
+    let render_bind_group = create_render_bind_group(
+        device,
+        &shared.layouts.render,
+        &texture_view,
+        &shared.texture_sampler,
+        display_buffer,
+    );
+    (
+        accumulation_textures,
+        render_bind_group,
+        compute_bind_groups,
+    )
+}
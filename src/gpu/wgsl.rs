@@ -0,0 +1,671 @@
+use std::fmt;
+
+use eframe::egui_wgpu::wgpu::{Buffer, Queue};
+
+/**
+ * Where a type is in a buffer, a uniform buffer aligns structs and arrays on 16 bytes
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    Uniform,
+    Storage,
+}
+
+/**
+ * A rust type that isn't laid out like its wgsl equivalent, the shader would read garbage
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// wgsl puts the field at another offset
+    Offset {
+        struct_name: &'static str,
+        field: &'static str,
+        rust: usize,
+        wgsl: usize,
+    },
+    /// wgsl gives the struct another size, usually because the padding at the end is missing
+    Size {
+        struct_name: &'static str,
+        rust: usize,
+        wgsl: usize,
+    },
+    /// the elements of an array in a uniform buffer have to be a multiple of 16 bytes apart
+    ArrayStride {
+        element: &'static str,
+        stride: usize,
+    },
+}
+
+impl LayoutError {
+    /// what went wrong without the details, a panic at compile time can't format them
+    pub const fn summary(&self) -> &'static str {
+        match self {
+            LayoutError::Offset { .. } => {
+                "a field of a gpu struct isn't at the offset wgsl gives it"
+            }
+            LayoutError::Size { .. } => "a gpu struct doesn't have the size wgsl gives it",
+            LayoutError::ArrayStride { .. } => {
+                "the elements of an array in a uniform buffer aren't a multiple of 16 bytes apart"
+            }
+        }
+    }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::Offset {
+                struct_name,
+                field,
+                rust,
+                wgsl,
+            } => write!(
+                f,
+                "{}.{} is at byte {} in rust and at byte {} in wgsl",
+                struct_name, field, rust, wgsl
+            ),
+            LayoutError::Size {
+                struct_name,
+                rust,
+                wgsl,
+            } => write!(
+                f,
+                "{} is {} bytes in rust and {} bytes in wgsl",
+                struct_name, rust, wgsl
+            ),
+            LayoutError::ArrayStride { element, stride } => write!(
+                f,
+                "an array of {} has a stride of {} bytes, it has to be a multiple of 16 in a uniform buffer",
+                element, stride
+            ),
+        }
+    }
+}
+
+/**
+ * The size and alignment of a type in wgsl, with whether what it's made of is laid out right
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct TypeLayout {
+    pub size: usize,
+    pub align: usize,
+    /// in a uniform buffer, the alignment of a struct or an array is rounded up to 16
+    pub uniform_align: usize,
+    /// in a uniform buffer, a struct takes at least a multiple of 16 bytes before the next field
+    pub uniform_size: usize,
+    pub storage: Result<(), LayoutError>,
+    pub uniform: Result<(), LayoutError>,
+}
+
+impl TypeLayout {
+    /// a number, a vector or a matrix
+    const fn plain(size: usize, align: usize) -> Self {
+        TypeLayout {
+            size,
+            align,
+            uniform_align: align,
+            uniform_size: size,
+            storage: Ok(()),
+            uniform: Ok(()),
+        }
+    }
+
+    /**
+     * The layout of a struct with the fields at their rust offsets and the size of the rust struct,
+     * checked against where wgsl puts them in both address spaces
+     */
+    pub const fn of_struct(name: &'static str, fields: &[WgslField], size: usize) -> Self {
+        let mut align = 1;
+        let mut uniform_align = 1;
+        let mut index = 0;
+        while index < fields.len() {
+            align = max(align, fields[index].layout.align);
+            uniform_align = max(uniform_align, fields[index].layout.uniform_align);
+            index += 1;
+        }
+        TypeLayout {
+            size,
+            align,
+            uniform_align: round_up(16, uniform_align),
+            uniform_size: round_up(16, size),
+            storage: check_struct(name, fields, size, AddressSpace::Storage),
+            uniform: check_struct(name, fields, size, AddressSpace::Uniform),
+        }
+    }
+
+    /**
+     * The layout of an array of structs, the size of a struct is already a multiple of its alignment
+     */
+    const fn of_array(element_name: &'static str, element: TypeLayout, length: usize) -> Self {
+        let uniform = if element.size % 16 != 0 {
+            Err(LayoutError::ArrayStride {
+                element: element_name,
+                stride: element.size,
+            })
+        } else {
+            element.uniform
+        };
+        TypeLayout {
+            size: element.size * length,
+            align: element.align,
+            uniform_align: round_up(16, element.uniform_align),
+            uniform_size: element.size * length,
+            storage: element.storage,
+            uniform,
+        }
+    }
+
+    pub const fn check(&self, address_space: AddressSpace) -> Result<(), LayoutError> {
+        match address_space {
+            AddressSpace::Uniform => self.uniform,
+            AddressSpace::Storage => self.storage,
+        }
+    }
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+const fn round_up(align: usize, size: usize) -> usize {
+    size.div_ceil(align) * align
+}
+
+/**
+ * Lays the fields out one after the other like wgsl does and compares it to the rust offsets
+ */
+const fn check_struct(
+    struct_name: &'static str,
+    fields: &[WgslField],
+    size: usize,
+    address_space: AddressSpace,
+) -> Result<(), LayoutError> {
+    let uniform = matches!(address_space, AddressSpace::Uniform);
+    // where the last field ends and where the next one can start, they're only different
+    // after a struct in a uniform buffer
+    let mut end = 0;
+    let mut next = 0;
+    let mut struct_align = 1;
+    let mut index = 0;
+    while index < fields.len() {
+        let field = &fields[index];
+        if let Err(error) = field.layout.check(address_space) {
+            return Err(error);
+        }
+        let (align, size) = if uniform {
+            (field.layout.uniform_align, field.layout.uniform_size)
+        } else {
+            (field.layout.align, field.layout.size)
+        };
+        let offset = round_up(align, next);
+        if field.offset != offset {
+            return Err(LayoutError::Offset {
+                struct_name,
+                field: field.name,
+                rust: field.offset,
+                wgsl: offset,
+            });
+        }
+        end = offset + field.layout.size;
+        next = offset + size;
+        // the size of a struct is rounded to its alignment, the one it has in every address space
+        struct_align = max(struct_align, field.layout.align);
+        index += 1;
+    }
+    let wgsl_size = round_up(struct_align, end);
+    if size != wgsl_size {
+        return Err(LayoutError::Size {
+            struct_name,
+            rust: size,
+            wgsl: wgsl_size,
+        });
+    }
+    Ok(())
+}
+
+/**
+ * A type with an equivalent in wgsl, the arrays of 2 to 4 numbers are vectors
+ * and the other arrays have to be arrays of structs
+ */
+pub trait WgslType {
+    const LAYOUT: TypeLayout;
+
+    fn name() -> String;
+}
+
+macro_rules! wgsl_type {
+    ($($type:ty => $name:literal, $size:literal, $align:literal;)*) => {
+        $(
+            impl WgslType for $type {
+                const LAYOUT: TypeLayout = TypeLayout::plain($size, $align);
+
+                fn name() -> String {
+                    String::from($name)
+                }
+            }
+        )*
+    };
+}
+
+wgsl_type! {
+    f32 => "f32", 4, 4;
+    u32 => "u32", 4, 4;
+    i32 => "i32", 4, 4;
+    [f32; 2] => "vec2f", 8, 8;
+    // a vec3 is aligned like a vec4 but the next field can use its last 4 bytes
+    [f32; 3] => "vec3f", 12, 16;
+    [f32; 4] => "vec4f", 16, 16;
+    [u32; 2] => "vec2u", 8, 8;
+    [u32; 3] => "vec3u", 12, 16;
+    [u32; 4] => "vec4u", 16, 16;
+    [[f32; 4]; 4] => "mat4x4f", 64, 16;
+}
+
+impl<T: WgslStruct, const N: usize> WgslType for [T; N] {
+    const LAYOUT: TypeLayout = TypeLayout::of_array(T::NAME, T::LAYOUT, N);
+
+    fn name() -> String {
+        format!("array<{}, {}>", T::NAME, N)
+    }
+}
+
+pub struct WgslField {
+    pub name: &'static str,
+    /// the name of the type in wgsl
+    pub ty: fn() -> String,
+    /// the lines of the doc comment, they are comments in the shader
+    pub doc: &'static [&'static str],
+    /// where the field is in the rust struct
+    pub offset: usize,
+    pub layout: TypeLayout,
+}
+
+/**
+ * A struct uploaded to the gpu, the shaders get its declaration from the rust one so they can't drift apart.
+ * The padding is declared too so the struct has the same size in both
+ */
+pub trait WgslStruct: bytemuck::Pod + WgslType {
+    const NAME: &'static str;
+    const FIELDS: &'static [WgslField];
+
+    fn declaration() -> String {
+        let mut declaration = format!("struct {} {{\n", Self::NAME);
+        for field in Self::FIELDS {
+            for line in field.doc {
+                declaration += &format!("    //{}\n", line);
+            }
+            declaration += &format!("    {}: {},\n", field.name, (field.ty)());
+        }
+        declaration + "}\n"
+    }
+}
+
+/**
+ * Fails to compile when the type isn't laid out like wgsl lays it out in the address space,
+ * the error says what kind of mistake it is and the notes say which type it is
+ */
+pub const fn assert_layout<T: WgslType>(address_space: AddressSpace) {
+    if let Err(error) = T::LAYOUT.check(address_space) {
+        panic!("{}", error.summary());
+    }
+}
+
+/**
+ * Writes a uniform, its type is checked against the wgsl layout rules when this is compiled
+ */
+pub fn write_uniform<T: WgslType + bytemuck::Pod>(queue: &Queue, buffer: &Buffer, value: &T) {
+    const { assert_layout::<T>(AddressSpace::Uniform) };
+    queue.write_buffer(buffer, 0, bytemuck::bytes_of(value));
+}
+
+/**
+ * Writes elements of a storage buffer starting at the element with this index,
+ * their type is checked against the wgsl layout rules when this is compiled
+ */
+pub fn write_storage<T: WgslType + bytemuck::Pod>(
+    queue: &Queue,
+    buffer: &Buffer,
+    first_element: usize,
+    elements: &[T],
+) {
+    const { assert_layout::<T>(AddressSpace::Storage) };
+    let offset = first_element * std::mem::size_of::<T>();
+    queue.write_buffer(buffer, offset as u64, bytemuck::cast_slice(elements));
+}
+
+/**
+ * Declares a struct uploaded to the gpu with the name it has in wgsl,
+ * the doc comments of the fields end up in the shaders too
+ */
+macro_rules! wgsl_struct {
+    (
+        $(#[$attribute:meta])*
+        pub struct $name:ident as $wgsl_name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                pub $field:ident: $type:ty,
+            )*
+        }
+    ) => {
+        $(#[$attribute])*
+        #[repr(C)]
+        #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+        pub struct $name {
+            $(
+                $(#[doc = $doc])*
+                pub $field: $type,
+            )*
+        }
+
+        impl $crate::gpu::wgsl::WgslStruct for $name {
+            const NAME: &'static str = stringify!($wgsl_name);
+            const FIELDS: &'static [$crate::gpu::wgsl::WgslField] = &[$(
+                $crate::gpu::wgsl::WgslField {
+                    name: stringify!($field),
+                    ty: <$type as $crate::gpu::wgsl::WgslType>::name,
+                    doc: &[$($doc),*],
+                    offset: std::mem::offset_of!($name, $field),
+                    layout: <$type as $crate::gpu::wgsl::WgslType>::LAYOUT,
+                },
+            )*];
+        }
+
+        impl $crate::gpu::wgsl::WgslType for $name {
+            const LAYOUT: $crate::gpu::wgsl::TypeLayout = $crate::gpu::wgsl::TypeLayout::of_struct(
+                stringify!($wgsl_name),
+                <$name as $crate::gpu::wgsl::WgslStruct>::FIELDS,
+                std::mem::size_of::<$name>(),
+            );
+
+            fn name() -> String {
+                String::from(stringify!($wgsl_name))
+            }
+        }
+    };
+}
+pub(crate) use wgsl_struct;
+
+/**
+ * Puts the declarations of the structs after the code of a shader, so the lines of the code
+ * keep their numbers in the errors. wgsl doesn't mind things being used before they are declared
+ */
+pub fn with_declarations(source: &str, declarations: &[String]) -> String {
+    let mut shader = format!("{}\n// declared from the rust structs\n", source);
+    for declaration in declarations {
+        shader += "\n";
+        shader += declaration;
+    }
+    shader
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressSpace, LayoutError, WgslStruct, WgslType};
+    use crate::gpu::compute_stage::compute_shader_source;
+    use crate::gpu::render_stage::{render_shader_source, DisplayUniform};
+    use crate::gpu::shader_modules::ShaderModules;
+    use crate::gpu::shared_stage_data::*;
+    use crate::gpu::DEFAULT_WORKGROUP_SIZE;
+    use eframe::egui_wgpu::wgpu::TextureFormat;
+
+    /**
+     * The shader has to be valid with the declarations of the structs
+     */
+    fn parse(source: &str) -> naga::Module {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|error| panic!("{}", error.emit_to_string(source)));
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .unwrap_or_else(|error| panic!("{}", error.emit_to_string(source)));
+        module
+    }
+
+    /**
+     * Fails when the struct doesn't have the same size in the shader
+     */
+    fn assert_same_size<T: WgslStruct>(module: &naga::Module) {
+        let span = module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match ty.inner {
+                naga::TypeInner::Struct { span, .. } if ty.name.as_deref() == Some(T::NAME) => {
+                    Some(span)
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("the shader doesn't have the struct {}", T::NAME));
+        assert_eq!(
+            span as usize,
+            std::mem::size_of::<T>(),
+            "{} doesn't have the same size in rust and in wgsl",
+            T::NAME
+        );
+    }
+
+    mod vec3 {
+        wgsl_struct! {
+            pub struct Packed as Packed {
+                pub position: [f32; 3],
+                pub radius: f32,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Unpadded as Unpadded {
+                pub position: [f32; 3],
+                pub normal: [f32; 3],
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Padded as Padded {
+                pub position: [f32; 3],
+                pub _padding0: f32,
+                pub normal: [f32; 3],
+                pub _padding1: f32,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Last as Last {
+                pub direction: [f32; 3],
+            }
+        }
+    }
+
+    mod arrays {
+        wgsl_struct! {
+            pub struct Small as Small {
+                pub uv: [f32; 2],
+            }
+        }
+
+        wgsl_struct! {
+            pub struct SmallArray as SmallArray {
+                pub elements: [Small; 4],
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Big as Big {
+                pub color: [f32; 4],
+            }
+        }
+
+        wgsl_struct! {
+            pub struct BigArray as BigArray {
+                pub count: u32,
+                pub _padding0: u32,
+                pub _padding1: u32,
+                pub _padding2: u32,
+                pub elements: [Big; 2],
+            }
+        }
+    }
+
+    mod nested {
+        wgsl_struct! {
+            pub struct Inner as Inner {
+                pub value: f32,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Outer as Outer {
+                pub inner: Inner,
+                pub after: f32,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Wrapper as Wrapper {
+                pub inner: Inner,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Misplaced as Misplaced {
+                pub before: f32,
+                pub inner: Packed,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Packed as Packed {
+                pub position: [f32; 3],
+                pub radius: f32,
+            }
+        }
+
+        wgsl_struct! {
+            pub struct Placed as Placed {
+                pub before: f32,
+                pub _padding0: u32,
+                pub _padding1: u32,
+                pub _padding2: u32,
+                pub inner: Packed,
+            }
+        }
+    }
+
+    #[test]
+    fn vec3_is_aligned_on_16_bytes_but_shares_them() {
+        use vec3::*;
+        for address_space in [AddressSpace::Uniform, AddressSpace::Storage] {
+            assert_eq!(Packed::LAYOUT.check(address_space), Ok(()));
+            assert_eq!(Padded::LAYOUT.check(address_space), Ok(()));
+            assert_eq!(
+                Unpadded::LAYOUT.check(address_space),
+                Err(LayoutError::Offset {
+                    struct_name: "Unpadded",
+                    field: "normal",
+                    rust: 12,
+                    wgsl: 16,
+                })
+            );
+            assert_eq!(
+                Last::LAYOUT.check(address_space),
+                Err(LayoutError::Size {
+                    struct_name: "Last",
+                    rust: 12,
+                    wgsl: 16,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn arrays_in_uniforms_need_a_stride_of_16_bytes() {
+        use arrays::*;
+        assert_eq!(SmallArray::LAYOUT.check(AddressSpace::Storage), Ok(()));
+        assert_eq!(
+            SmallArray::LAYOUT.check(AddressSpace::Uniform),
+            Err(LayoutError::ArrayStride {
+                element: "Small",
+                stride: 8,
+            })
+        );
+        assert_eq!(BigArray::LAYOUT.check(AddressSpace::Uniform), Ok(()));
+        assert_eq!(BigArray::LAYOUT.check(AddressSpace::Storage), Ok(()));
+        assert!(BigArray::declaration().contains("elements: array<Big, 2>,"));
+    }
+
+    #[test]
+    fn nested_structs_in_uniforms_take_16_bytes() {
+        use nested::*;
+        assert_eq!(Outer::LAYOUT.check(AddressSpace::Storage), Ok(()));
+        assert_eq!(
+            Outer::LAYOUT.check(AddressSpace::Uniform),
+            Err(LayoutError::Offset {
+                struct_name: "Outer",
+                field: "after",
+                rust: 4,
+                wgsl: 16,
+            })
+        );
+        for address_space in [AddressSpace::Uniform, AddressSpace::Storage] {
+            // only what comes after a struct is pushed, the struct around it keeps its size
+            assert_eq!(Wrapper::LAYOUT.check(address_space), Ok(()));
+            assert_eq!(
+                Misplaced::LAYOUT.check(address_space),
+                Err(LayoutError::Offset {
+                    struct_name: "Misplaced",
+                    field: "inner",
+                    rust: 4,
+                    wgsl: 16,
+                })
+            );
+            assert_eq!(Placed::LAYOUT.check(address_space), Ok(()));
+        }
+    }
+
+    #[test]
+    fn the_structs_of_the_shaders_follow_the_layout_rules() {
+        for layout in [
+            SharedStageUniform::LAYOUT,
+            GpuCamera::LAYOUT,
+            DisplayUniform::LAYOUT,
+        ] {
+            assert_eq!(layout.check(AddressSpace::Uniform), Ok(()));
+        }
+        for layout in [
+            GpuSphere::LAYOUT,
+            GpuMaterial::LAYOUT,
+            GpuVertex::LAYOUT,
+            GpuTriangle::LAYOUT,
+            GpuInstance::LAYOUT,
+            GpuBvhNode::LAYOUT,
+        ] {
+            assert_eq!(layout.check(AddressSpace::Storage), Ok(()));
+        }
+    }
+
+    #[test]
+    fn compute_shader_structs_have_the_size_of_the_rust_ones() {
+        let shader = compute_shader_source(&ShaderModules::embedded(), DEFAULT_WORKGROUP_SIZE);
+        let module = parse(&shader.unwrap().code);
+        assert_same_size::<SharedStageUniform>(&module);
+        assert_same_size::<GpuCamera>(&module);
+        assert_same_size::<GpuSphere>(&module);
+        assert_same_size::<GpuMaterial>(&module);
+        assert_same_size::<GpuVertex>(&module);
+        assert_same_size::<GpuTriangle>(&module);
+        assert_same_size::<GpuInstance>(&module);
+        assert_same_size::<GpuBvhNode>(&module);
+    }
+
+    #[test]
+    fn render_shader_structs_have_the_size_of_the_rust_ones() {
+        let shader = render_shader_source(&ShaderModules::embedded(), TextureFormat::Rgba8Unorm);
+        let module = parse(&shader.unwrap().code);
+        assert_same_size::<SharedStageUniform>(&module);
+        assert_same_size::<DisplayUniform>(&module);
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use eframe::egui;
+use eframe::egui_wgpu::wgpu::*;
+
+use crate::gpu::{self, DisplaySettings, RenderResources, ViewportResources};
+use crate::ray_tracer::scene::Scene;
+
+/**
+ * Renders images without a window, with the same pipelines as the widget
+ */
+pub struct Renderer {
+    device: Device,
+    queue: Queue,
+    adapter_info: AdapterInfo,
+    resources: RenderResources,
+    /// every scene is a new revision so the buffers are always rewritten
+    scene_revision: u64,
+    max_depth: u32,
+}
+
+/// there is a single image, so a single viewport
+const VIEWPORT: &str = "headless";
+
+impl Renderer {
+    /**
+     * A renderer on the gpu, or on the software adapter when there is no gpu or when it's asked for
+     */
+    pub fn new(software: bool) -> Result<Self, String> {
+        pollster::block_on(Self::new_async(software))
+    }
+
+    pub async fn new_async(software: bool) -> Result<Self, String> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: util::backend_bits_from_env().unwrap_or(Backends::all()),
+            ..Default::default()
+        });
+        let mut adapter = None;
+        if !software {
+            adapter = instance
+                .request_adapter(&RequestAdapterOptions::default())
+                .await;
+        }
+        if adapter.is_none() {
+            adapter = instance
+                .request_adapter(&RequestAdapterOptions {
+                    force_fallback_adapter: true,
+                    ..Default::default()
+                })
+                .await;
+        }
+        let adapter = adapter.ok_or("no adapter found, not even a software one")?;
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    label: Some("Headless device"),
+                    features: Features::empty(),
+                    // the compute stage uses more storage buffers than the downlevel limits have
+                    limits: adapter.limits(),
+                },
+                None,
+            )
+            .await
+            .map_err(|error| error.to_string())?;
+        Ok(Self::with_device(device, queue, adapter.get_info()))
+    }
+
+    /**
+     * A renderer on a device that already exists
+     */
+    pub fn with_device(device: Device, queue: Queue, adapter_info: AdapterInfo) -> Self {
+        // the image is painted in rgba when it's read back, not with this pipeline
+        let mut resources = gpu::get_render_resources(&device, TextureFormat::Rgba8Unorm);
+        let viewport = ViewportResources::new(&device, &resources.shared);
+        resources
+            .viewports
+            .insert(egui::Id::new(VIEWPORT), viewport);
+        Renderer {
+            device,
+            queue,
+            adapter_info,
+            resources,
+            scene_revision: gpu::new_scene_revision(),
+            max_depth: 0,
+        }
+    }
+
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// the width and height an image can have at most
+    pub fn max_image_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /**
+     * Starts a new image of the scene seen by its camera
+     */
+    pub fn set_scene(&mut self, scene: &Scene, size: [u32; 2]) -> Result<(), String> {
+        let max_image_size = self.max_image_size();
+        if size.iter().any(|&size| size == 0 || size > max_image_size) {
+            return Err(format!(
+                "the image has to be between 1 and {} pixels wide and high on this device",
+                max_image_size
+            ));
+        }
+        // the copy is what the gpu gets, so its bvh is rebuilt in case the spheres or instances changed
+        let mut scene = scene.clone();
+        scene.rebuild_bvh();
+        self.scene_revision = gpu::new_scene_revision();
+        self.max_depth = scene.settings.max_depth;
+        self.resources
+            .upload_scene(&self.device, &self.queue, &scene, self.scene_revision);
+        let RenderResources { shared, viewports } = &mut self.resources;
+        let viewport = viewports.get_mut(&egui::Id::new(VIEWPORT)).unwrap();
+        viewport.fit_textures(&self.device, shared, size);
+        viewport.start_image(
+            &self.queue,
+            &scene,
+            self.scene_revision,
+            &scene.camera,
+            size,
+        );
+        Ok(())
+    }
+
+    pub fn set_display(&mut self, display: &DisplaySettings) {
+        let viewport = self.resources.viewports.get_mut(&egui::Id::new(VIEWPORT));
+        viewport.unwrap().set_display(&self.queue, display);
+    }
+
+    /**
+     * Adds samples to the image, waiting for the gpu
+     */
+    pub fn render(&mut self, samples: u32) {
+        for _ in 0..samples {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("Headless render encoder"),
+                });
+            let RenderResources { shared, viewports } = &mut self.resources;
+            let viewport = viewports.get_mut(&egui::Id::new(VIEWPORT)).unwrap();
+            viewport.render_sample(&self.queue, &mut encoder, shared, self.max_depth);
+            self.queue.submit(Some(encoder.finish()));
+            // so the uniform of the next sample isn't written before this one is computed
+            self.device.poll(Maintain::Wait);
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.viewport().sample_count
+    }
+
+    /// the image like it's displayed, in rgba bytes row after row
+    pub fn read_display(&self) -> Vec<u8> {
+        gpu::read_display(
+            &self.device,
+            &self.queue,
+            &self.resources.shared,
+            self.viewport(),
+        )
+    }
+
+    /// the average of the samples in rgba floats row after row, nothing before the first sample
+    pub fn read_radiance(&self) -> Option<Vec<f32>> {
+        gpu::read_radiance(&self.device, &self.queue, self.viewport())
+    }
+
+    /**
+     * Saves the image in the format of the extension of the path
+     */
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        crate::export::save_render(
+            path,
+            &self.device,
+            &self.queue,
+            &self.resources.shared,
+            self.viewport(),
+        )
+    }
+
+    fn viewport(&self) -> &ViewportResources {
+        &self.resources.viewports[&egui::Id::new(VIEWPORT)]
+    }
+}
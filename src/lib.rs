@@ -0,0 +1,16 @@
+//! A real time ray tracer running in a compute shader.
+//!
+//! [`RayTracerWidget`] paints it in an egui ui, in as many views as needed,
+//! and [`Renderer`] renders images without a window.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod export;
+pub mod gpu;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+pub mod ray_tracer;
+mod widget;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use headless::Renderer;
+pub use widget::{RayTracerWidget, ViewSettings};
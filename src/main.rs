@@ -1,19 +1,45 @@
 mod app;
-mod gpu;
-mod ray_tracer;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
 
-use eframe;
 use eframe::egui_wgpu::wgpu;
 use std::sync::Arc;
 
+use rt_shader::ray_tracer::scene::Scene;
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<(), eframe::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("render") {
+        if let Err(error) = cli::render(&args[1..]) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // otherwise the only argument is an optional scene file to open
+    let scene = match args.first() {
+        Some(path) => {
+            match rt_shader::ray_tracer::scene_file::load_scene(std::path::Path::new(path)) {
+                Ok(scene) => scene,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => Scene::example(),
+    };
+
     let options = eframe::NativeOptions {
         renderer: eframe::Renderer::Wgpu,
         multisampling: 1,
         wgpu_options: eframe::egui_wgpu::WgpuConfiguration {
-            device_descriptor: Arc::new(|_| wgpu::DeviceDescriptor {
-                features: wgpu::Features::TIMESTAMP_QUERY,
+            // timestamps are only requested when the adapter has them, the render time is
+            // measured on the cpu otherwise
+            device_descriptor: Arc::new(|adapter| wgpu::DeviceDescriptor {
+                features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
                 ..Default::default()
             }),
             ..Default::default()
@@ -24,7 +50,7 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "real time ray tracer",
         options,
-        Box::new(|cc| Box::new(app::AppUI::new(cc))),
+        Box::new(|cc| Box::new(app::AppUI::new(cc, scene))),
     )
 }
 
@@ -40,7 +66,7 @@ fn main() {
             .start(
                 "the_canvas_id", // hardcode it
                 web_options,
-                Box::new(|cc| Box::new(app::AppUI::new(cc))),
+                Box::new(|cc| Box::new(app::AppUI::new(cc, Scene::example()))),
             )
             .await
             .expect("failed to start eframe");
@@ -0,0 +1,490 @@
+use web_time::{Duration, Instant};
+
+use super::ray::Ray;
+use super::transform::Matrix4;
+use super::vectors::{Point3, Vec3};
+
+/// the shader uses a fixed size stack to go through the tree so it can't be deeper than this
+pub const MAX_BVH_DEPTH: usize = 64;
+const BIN_COUNT: usize = 12;
+/// the cost of going through a node compared to the cost of intersecting a primitive
+const TRAVERSAL_COST: f32 = 1.0;
+
+/**
+ * An axis aligned bounding box
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /**
+     * A box containing nothing, growing it with anything gives that thing
+     */
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3 {
+            x: f32::INFINITY,
+            y: f32::INFINITY,
+            z: f32::INFINITY,
+        },
+        max: Vec3 {
+            x: f32::NEG_INFINITY,
+            y: f32::NEG_INFINITY,
+            z: f32::NEG_INFINITY,
+        },
+    };
+
+    pub fn from_points(points: &[Point3<f32>]) -> Self {
+        points
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, &point| aabb.grow(point))
+    }
+
+    #[inline]
+    pub fn grow(self, point: Point3<f32>) -> Self {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    #[inline]
+    pub fn union(self, other: Aabb) -> Self {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /**
+     * The box containing this box once transformed
+     */
+    pub fn transform(&self, matrix: &Matrix4) -> Self {
+        if self.min.x > self.max.x {
+            return *self; // empty box
+        }
+        let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+            let x = if corner & 1 == 0 {
+                self.min.x
+            } else {
+                self.max.x
+            };
+            let y = if corner & 2 == 0 {
+                self.min.y
+            } else {
+                self.max.y
+            };
+            let z = if corner & 4 == 0 {
+                self.min.z
+            } else {
+                self.max.z
+            };
+            matrix.transform_point(Point3::new(x, y, z))
+        });
+        Aabb::from_points(&corners)
+    }
+
+    /**
+     * The slab test, gives the distance where the ray enters the box
+     */
+    pub fn hit(&self, ray: &Ray, inverse_direction: Vec3<f32>, max_distance: f32) -> Option<f32> {
+        let mut enter = 0.0f32;
+        let mut exit = max_distance;
+        for axis in 0..3 {
+            let to_min = (self.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+            let to_max = (self.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+            enter = enter.max(to_min.min(to_max));
+            exit = exit.min(to_min.max(to_max));
+        }
+        (enter <= exit).then_some(enter)
+    }
+
+    #[inline]
+    pub fn centroid(&self) -> Point3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    /**
+     * The surface area, which is proportional to the chances of a random ray hitting the box
+     */
+    #[inline]
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        if size.x < 0.0 {
+            return 0.0; // empty box
+        }
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+}
+
+/**
+ * A node of the flattened tree. The two children of a node are always next to each other
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BvhNode {
+    pub bounds: Aabb,
+    /// the index of the left child, or of the first primitive for a leaf
+    pub left_or_first: u32,
+    /// the number of primitives, 0 when the node isn't a leaf
+    pub primitive_count: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    /// the root is the first node, there are no nodes when there are no primitives
+    pub nodes: Vec<BvhNode>,
+    /// the leaves point to ranges of this, which points to the primitives given to the builder
+    pub primitive_indices: Vec<u32>,
+    pub build_time: Duration,
+}
+
+impl Bvh {
+    /**
+     * The box around everything in the tree
+     */
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |root| root.bounds)
+    }
+
+    /**
+     * The distance to the closest primitive the ray hits, like the traversal of the shader.
+     * hit_primitive gives the distance to a primitive if it's hit closer than the maximum distance
+     */
+    pub fn closest_hit(
+        &self,
+        ray: &Ray,
+        mut hit_primitive: impl FnMut(u32, f32) -> Option<f32>,
+    ) -> Option<f32> {
+        let inverse_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let mut closest: Option<f32> = None;
+        let mut stack = Vec::with_capacity(MAX_BVH_DEPTH);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node: &BvhNode = &self.nodes[index];
+            let max_distance = closest.unwrap_or(f32::INFINITY);
+            if node
+                .bounds
+                .hit(ray, inverse_direction, max_distance)
+                .is_none()
+            {
+                continue;
+            }
+            if node.primitive_count == 0 {
+                stack.push(node.left_or_first as usize);
+                stack.push(node.left_or_first as usize + 1);
+                continue;
+            }
+            let first = node.left_or_first as usize;
+            for &primitive in &self.primitive_indices[first..first + node.primitive_count as usize]
+            {
+                let max_distance = closest.unwrap_or(f32::INFINITY);
+                if let Some(distance) = hit_primitive(primitive, max_distance) {
+                    closest = Some(distance);
+                }
+            }
+        }
+        closest
+    }
+
+    /**
+     * Builds the tree with the surface area heuristic, testing a few split positions
+     * (bins) on every axis for every node
+     */
+    pub fn build(primitive_bounds: &[Aabb]) -> Self {
+        let start = Instant::now();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(primitive_bounds.len() * 2),
+            primitive_indices: (0..primitive_bounds.len() as u32).collect(),
+            build_time: Duration::ZERO,
+        };
+        if primitive_bounds.is_empty() {
+            return bvh;
+        }
+
+        let centroids: Vec<Point3<f32>> = primitive_bounds.iter().map(Aabb::centroid).collect();
+        bvh.nodes.push(BvhNode {
+            bounds: Aabb::EMPTY,
+            left_or_first: 0,
+            primitive_count: primitive_bounds.len() as u32,
+        });
+
+        // (node index, depth) of the nodes left to split
+        let mut to_split = vec![(0usize, 0usize)];
+        while let Some((node_index, depth)) = to_split.pop() {
+            let node = &mut bvh.nodes[node_index];
+            let first = node.left_or_first as usize;
+            let count = node.primitive_count as usize;
+            let primitives = &mut bvh.primitive_indices[first..first + count];
+            node.bounds = primitives.iter().fold(Aabb::EMPTY, |bounds, &index| {
+                bounds.union(primitive_bounds[index as usize])
+            });
+
+            if count <= 1 || depth + 1 >= MAX_BVH_DEPTH {
+                continue;
+            }
+            let leaf_cost = count as f32 * node.bounds.surface_area();
+            let Some(split) = find_best_split(primitives, primitive_bounds, &centroids) else {
+                continue;
+            };
+            if split.cost + TRAVERSAL_COST * node.bounds.surface_area() >= leaf_cost {
+                continue;
+            }
+
+            // move the primitives on the left of the split at the start
+            let mut left_count = 0;
+            for index in 0..primitives.len() {
+                if split.is_left(centroids[primitives[index] as usize]) {
+                    primitives.swap(index, left_count);
+                    left_count += 1;
+                }
+            }
+            if left_count == 0 || left_count == count {
+                continue;
+            }
+
+            let left_index = bvh.nodes.len();
+            bvh.nodes[node_index].left_or_first = left_index as u32;
+            bvh.nodes[node_index].primitive_count = 0;
+            bvh.nodes.push(BvhNode {
+                bounds: Aabb::EMPTY,
+                left_or_first: first as u32,
+                primitive_count: left_count as u32,
+            });
+            bvh.nodes.push(BvhNode {
+                bounds: Aabb::EMPTY,
+                left_or_first: (first + left_count) as u32,
+                primitive_count: (count - left_count) as u32,
+            });
+            to_split.push((left_index, depth + 1));
+            to_split.push((left_index + 1, depth + 1));
+        }
+
+        bvh.build_time = start.elapsed();
+        bvh
+    }
+}
+
+struct Split {
+    axis: usize,
+    centroid_min: f32,
+    bin_scale: f32,
+    /// the primitives in the bins before this one go to the left
+    bin: usize,
+    cost: f32,
+}
+
+impl Split {
+    #[inline]
+    fn bin_of(axis_min: f32, bin_scale: f32, centroid: f32) -> usize {
+        (((centroid - axis_min) * bin_scale) as usize).min(BIN_COUNT - 1)
+    }
+
+    #[inline]
+    fn is_left(&self, centroid: Point3<f32>) -> bool {
+        Split::bin_of(self.centroid_min, self.bin_scale, centroid[self.axis]) < self.bin
+    }
+}
+
+/**
+ * Puts the centroids in bins along each axis and finds the split between two bins
+ * with the lowest surface area heuristic cost
+ */
+fn find_best_split(
+    primitives: &[u32],
+    primitive_bounds: &[Aabb],
+    centroids: &[Point3<f32>],
+) -> Option<Split> {
+    let centroid_bounds = primitives.iter().fold(Aabb::EMPTY, |bounds, &index| {
+        bounds.grow(centroids[index as usize])
+    });
+
+    let mut best: Option<Split> = None;
+    for axis in 0..3 {
+        let axis_min = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - axis_min;
+        if extent <= 0.0 {
+            continue; // all the centroids are on the same plane
+        }
+        let bin_scale = BIN_COUNT as f32 / extent;
+
+        let mut bin_bounds = [Aabb::EMPTY; BIN_COUNT];
+        let mut bin_counts = [0usize; BIN_COUNT];
+        for &index in primitives {
+            let centroid = centroids[index as usize];
+            let bin = Split::bin_of(axis_min, bin_scale, centroid[axis]);
+            bin_bounds[bin] = bin_bounds[bin].union(primitive_bounds[index as usize]);
+            bin_counts[bin] += 1;
+        }
+
+        // the cost of the left side of each split, sweeping from the left
+        let mut left_costs = [0.0; BIN_COUNT];
+        let mut bounds = Aabb::EMPTY;
+        let mut count = 0;
+        for bin in 1..BIN_COUNT {
+            bounds = bounds.union(bin_bounds[bin - 1]);
+            count += bin_counts[bin - 1];
+            left_costs[bin] = count as f32 * bounds.surface_area();
+        }
+
+        // then add the cost of the right side sweeping from the right
+        let mut bounds = Aabb::EMPTY;
+        let mut count = 0;
+        for bin in (1..BIN_COUNT).rev() {
+            bounds = bounds.union(bin_bounds[bin]);
+            count += bin_counts[bin];
+            let cost = left_costs[bin] + count as f32 * bounds.surface_area();
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(Split {
+                    axis,
+                    centroid_min: axis_min,
+                    bin_scale,
+                    bin,
+                    cost,
+                });
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aabb, Bvh, MAX_BVH_DEPTH};
+    use crate::ray_tracer::ray::Ray;
+    use crate::ray_tracer::vectors::{Point3, Vec3};
+
+    fn cube(center: Point3<f32>, half_size: f32) -> Aabb {
+        Aabb {
+            min: center - Vec3::splat(half_size),
+            max: center + Vec3::splat(half_size),
+        }
+    }
+
+    /// a grid of small cubes with a few big ones across it and some on top of each other
+    fn boxes() -> Vec<Aabb> {
+        let mut boxes = Vec::new();
+        for x in 0..6 {
+            for y in 0..4 {
+                for z in 0..5 {
+                    let center = Point3::new(x as f32, y as f32 * 1.5, z as f32 * 0.7);
+                    boxes.push(cube(center, 0.3));
+                }
+            }
+        }
+        boxes.push(cube(Point3::new(2.5, 2.0, 1.5), 3.0));
+        boxes.push(cube(Point3::new(0.0, 0.0, 0.0), 0.3));
+        boxes.push(cube(Point3::new(0.0, 0.0, 0.0), 0.3));
+        boxes
+    }
+
+    fn contains(outer: &Aabb, inner: &Aabb) -> bool {
+        (0..3).all(|axis| outer.min[axis] <= inner.min[axis] && inner.max[axis] <= outer.max[axis])
+    }
+
+    /**
+     * Goes through the tree checking the bounds of every node,
+     * gives how many leaves every primitive is in and the number of levels
+     */
+    fn check_tree(bvh: &Bvh, boxes: &[Aabb]) -> (Vec<usize>, usize) {
+        let mut leaf_counts = vec![0; boxes.len()];
+        let mut levels = 0;
+        let mut stack = vec![(0, 1)];
+        while let Some((index, level)) = stack.pop() {
+            let node = &bvh.nodes[index];
+            levels = levels.max(level);
+            if node.primitive_count == 0 {
+                for child in [node.left_or_first, node.left_or_first + 1] {
+                    let child_bounds = &bvh.nodes[child as usize].bounds;
+                    assert!(contains(&node.bounds, child_bounds), "node {}", index);
+                    stack.push((child as usize, level + 1));
+                }
+                continue;
+            }
+            let first = node.left_or_first as usize;
+            for &primitive in &bvh.primitive_indices[first..first + node.primitive_count as usize] {
+                assert!(contains(&node.bounds, &boxes[primitive as usize]));
+                leaf_counts[primitive as usize] += 1;
+            }
+        }
+        (leaf_counts, levels)
+    }
+
+    #[test]
+    fn every_primitive_is_in_one_leaf_inside_its_parents() {
+        let boxes = boxes();
+        let bvh = Bvh::build(&boxes);
+        let (leaf_counts, levels) = check_tree(&bvh, &boxes);
+        assert!(
+            leaf_counts.iter().all(|&count| count == 1),
+            "{:?}",
+            leaf_counts
+        );
+        assert!(levels > 1 && levels <= MAX_BVH_DEPTH, "{} levels", levels);
+        assert_eq!(
+            bvh.bounds(),
+            boxes.iter().fold(Aabb::EMPTY, |a, b| a.union(*b))
+        );
+    }
+
+    #[test]
+    fn the_tree_is_not_deeper_than_the_stack_of_the_shader() {
+        // every box is twice as far as the previous one so each split only takes a few of them away,
+        // the tree is a lot deeper than a balanced one
+        let boxes: Vec<_> = (0..100)
+            .map(|index| cube(Point3::new(2f32.powi(index), 0.0, 0.0), 0.5))
+            .collect();
+        let bvh = Bvh::build(&boxes);
+        let (leaf_counts, levels) = check_tree(&bvh, &boxes);
+        assert!(leaf_counts.iter().all(|&count| count == 1));
+        assert!(levels <= MAX_BVH_DEPTH, "{} levels", levels);
+    }
+
+    #[test]
+    fn an_empty_tree_has_no_nodes() {
+        let bvh = Bvh::build(&[]);
+        assert!(bvh.nodes.is_empty());
+        let ray = Ray::new(Point3::splat(0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(bvh.closest_hit(&ray, |_, _| Some(0.0)), None);
+    }
+
+    #[test]
+    fn the_closest_hit_is_the_one_of_a_loop_over_every_primitive() {
+        let boxes = boxes();
+        let bvh = Bvh::build(&boxes);
+        // a small linear congruential generator so the rays are the same every time
+        let mut state = 1u32;
+        let mut random = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        };
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = Point3::new(random() * 10.0, random() * 10.0, random() * 10.0);
+            // towards a point around the grid so most rays hit something
+            let target = Point3::new(random() * 4.0 + 2.5, random() * 4.0 + 2.0, random() * 4.0);
+            let direction = target - origin;
+            let ray = Ray::new(origin, direction);
+            let inverse_direction =
+                Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+            let hit = |index: u32, max_distance: f32| {
+                boxes[index as usize].hit(&ray, inverse_direction, max_distance)
+            };
+
+            let expected = (0..boxes.len() as u32)
+                .filter_map(|index| hit(index, f32::INFINITY))
+                .min_by(f32::total_cmp);
+            assert_eq!(bvh.closest_hit(&ray, hit), expected, "{:?}", ray);
+            hits += expected.is_some() as usize;
+        }
+        // some rays miss everything
+        assert!(hits > 100 && hits < 500, "{} hits", hits);
+    }
+}
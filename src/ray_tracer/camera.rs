@@ -1,6 +1,149 @@
+use std::f32::consts::PI;
+
 use eframe::epaint::Vec2;
+use serde::{Deserialize, Serialize};
+
+use super::ray::Ray;
+use super::validators::{field_of_view, non_negative, positive};
+use super::vectors::{Point3, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub look_at: Point3<f32>,
+    pub up: Vec3<f32>,
+    /// the vertical field of view in degrees
+    #[serde(deserialize_with = "field_of_view")]
+    pub vertical_fov: f32,
+    /// the diameter of the lens, 0 keeps everything in focus
+    #[serde(deserialize_with = "non_negative")]
+    pub aperture: f32,
+    /// the distance from the camera to the plane where things are sharp
+    #[serde(deserialize_with = "positive")]
+    pub focus_distance: f32,
+}
+
+impl Default for Camera {
+    /**
+     * The camera that used to be hardcoded in the shader,
+     * at the origin looking down -z with a viewport 2 units high at a distance of 1
+     */
+    fn default() -> Self {
+        Camera {
+            position: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            vertical_fov: 90.0,
+            aperture: 0.0,
+            focus_distance: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    /**
+     * The direction the camera looks in
+     */
+    pub fn forward(&self) -> Vec3<f32> {
+        (self.look_at - self.position).unit()
+    }
+
+    /**
+     * The direction to the right of the image
+     */
+    pub fn right(&self) -> Vec3<f32> {
+        self.forward().cross(self.up).unit()
+    }
+
+    /**
+     * The ray going from the center of the lens through a point of the image, (0, 0) is the top left
+     * and (1, 1) the bottom right. The distance along the ray is the distance along the forward direction
+     */
+    pub fn ray_through(&self, image_position: Vec2, aspect_ratio: f32) -> Ray {
+        let forward = self.forward();
+        let right = self.right();
+        let up = right.cross(forward);
+        let half_height = (self.vertical_fov.to_radians() / 2.0).tan();
+        let x = (2.0 * image_position.x - 1.0) * half_height * aspect_ratio;
+        let y = (1.0 - 2.0 * image_position.y) * half_height;
+        Ray::new(self.position, forward + right * x + up * y)
+    }
+
+    /**
+     * Turns the camera around the point it looks at, the yaw goes around the up vector
+     * and the pitch goes over the point. The angles are in radians
+     */
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let offset = rotate_around(self.position - self.look_at, self.up, yaw, pitch);
+        self.position = self.look_at + offset;
+    }
+
+    /**
+     * The same camera turned around the point it looks at until it's at this angle from the up vector,
+     * 0 looks straight down, as close as the orbit allows, and PI / 2 is level with the point
+     */
+    pub fn at_angle_from_up(&self, angle: f32) -> Camera {
+        let mut camera = *self;
+        let offset = self.position - self.look_at;
+        let angle_to_up = offset.unit().dot(self.up.unit()).clamp(-1.0, 1.0).acos();
+        camera.orbit(0.0, angle_to_up - angle);
+        camera
+    }
+
+    /**
+     * Turns the camera on itself, like turning the head. The angles are in radians
+     */
+    pub fn look_around(&mut self, yaw: f32, pitch: f32) {
+        let direction = rotate_around(self.look_at - self.position, self.up, yaw, -pitch);
+        self.look_at = self.position + direction;
+    }
+
+    /**
+     * Moves the camera towards the point it looks at, a factor under 1 gets closer
+     */
+    pub fn zoom(&mut self, factor: f32) {
+        let offset = self.position - self.look_at;
+        let distance = (offset.length() * factor).max(MIN_DISTANCE);
+        self.position = self.look_at + offset.unit() * distance;
+    }
+
+    /**
+     * Moves the camera and the point it looks at, the movement is in the
+     * space of the camera with x to the right, y up and z forward
+     */
+    pub fn fly(&mut self, movement: Vec3<f32>) {
+        let translation =
+            self.right() * movement.x + self.up.unit() * movement.y + self.forward() * movement.z;
+        self.position = self.position + translation;
+        self.look_at = self.look_at + translation;
+    }
+}
+
+/// the camera can't be closer than this to the point it looks at
+const MIN_DISTANCE: f32 = 0.01;
+/// the closest angle to the up vector, looking straight up makes the image spin
+const MIN_PITCH_ANGLE: f32 = 0.01;
+
+/**
+ * Rotates the vector around the up vector by the yaw and then towards or away from it by the pitch
+ */
+fn rotate_around(vector: Vec3<f32>, up: Vec3<f32>, yaw: f32, pitch: f32) -> Vec3<f32> {
+    let up = up.unit();
+    let vector = rotate(vector, up, yaw);
+    let angle_to_up = vector.unit().dot(up).clamp(-1.0, 1.0).acos();
+    let new_angle = (angle_to_up - pitch).clamp(MIN_PITCH_ANGLE, PI - MIN_PITCH_ANGLE);
+    let side = vector.cross(up);
+    if side.length_squared() == 0.0 {
+        return vector;
+    }
+    rotate(vector, side.unit(), angle_to_up - new_angle)
+}
 
-pub const OUTPUT_TEXTURE_WIDTH: u32 = 1920;
-pub const OUTPUT_TEXTURE_HEIGHT: u32 = 1080;
-pub const OUTPUT_TEXTURE_DIMENTIONS: Vec2 =
-    Vec2::new(OUTPUT_TEXTURE_WIDTH as f32, OUTPUT_TEXTURE_HEIGHT as f32);
+/**
+ * Rodrigues' rotation formula, the axis has to be a unit vector
+ */
+fn rotate(vector: Vec3<f32>, axis: Vec3<f32>, angle: f32) -> Vec3<f32> {
+    let (sin, cos) = angle.sin_cos();
+    vector * cos + axis.cross(vector) * sin + axis * (axis.dot(vector) * (1.0 - cos))
+}
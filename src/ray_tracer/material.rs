@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use super::validators::{albedo, emission, non_negative, positive, unit_interval};
+use super::vectors::Color;
+
+/**
+ * How the light bounces when a ray hits a surface
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Material {
+    /// a matte surface scattering the light in every direction
+    Lambertian {
+        #[serde(deserialize_with = "albedo")]
+        albedo: Color,
+    },
+    /// a reflective surface, the fuzz (from 0 to 1) blurs the reflection
+    Metal {
+        #[serde(deserialize_with = "albedo")]
+        albedo: Color,
+        #[serde(default, deserialize_with = "unit_interval")]
+        fuzz: f32,
+    },
+    /// glass, water and other transparent surfaces
+    Dielectric {
+        #[serde(deserialize_with = "positive")]
+        refraction_index: f32,
+    },
+    /// a surface giving off light
+    Emissive {
+        #[serde(deserialize_with = "emission")]
+        color: Color,
+        #[serde(default = "default_strength", deserialize_with = "non_negative")]
+        strength: f32,
+    },
+}
+
+fn default_strength() -> f32 {
+    1.0
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        }
+    }
+}
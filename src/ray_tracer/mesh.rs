@@ -0,0 +1,82 @@
+use super::bvh::{Aabb, Bvh};
+use super::ray::Ray;
+use super::vectors::{Point3, Vec3};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vertex {
+    pub position: Point3<f32>,
+    /// a zero normal means the mesh has no normals and the normal of the face is used
+    pub normal: Vec3<f32>,
+    pub uv: [f32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Triangle {
+    /// indices in the vertices of the mesh
+    pub indices: [u32; 3],
+    /// index in the materials of the scene
+    pub material: u32,
+}
+
+/**
+ * The geometry of a mesh, which can be placed many times in the scene with instances
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub triangles: Vec<Triangle>,
+    /// the bottom level bvh of the triangles, built once when the mesh is created
+    pub bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(name: String, vertices: Vec<Vertex>, triangles: Vec<Triangle>) -> Self {
+        let triangle_bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|triangle| {
+                Aabb::from_points(
+                    &triangle
+                        .indices
+                        .map(|index| vertices[index as usize].position),
+                )
+            })
+            .collect();
+        Mesh {
+            name,
+            bvh: Bvh::build(&triangle_bounds),
+            vertices,
+            triangles,
+        }
+    }
+
+    /**
+     * The distance where the ray hits a triangle with the Möller–Trumbore algorithm.
+     * It's not watertight like the shader but it's good enough to pick things with the mouse
+     */
+    pub fn hit_triangle(&self, triangle: u32, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let [a, b, c] = self.triangles[triangle as usize]
+            .indices
+            .map(|index| self.vertices[index as usize].position);
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = ray.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None; // the ray is parallel to the triangle
+        }
+        let inverse_determinant = 1.0 / determinant;
+        let to_origin = ray.origin - a;
+        let u = to_origin.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(edge1);
+        let v = ray.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = edge2.dot(q) * inverse_determinant;
+        (distance > 0.0 && distance < max_distance).then_some(distance)
+    }
+}
@@ -1,2 +1,11 @@
+pub mod bvh;
 pub mod camera;
-mod vectors;
+pub mod material;
+pub mod mesh;
+pub mod obj_import;
+pub mod ray;
+pub mod scene;
+pub mod scene_file;
+pub mod transform;
+pub mod validators;
+pub mod vectors;
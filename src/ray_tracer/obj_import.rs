@@ -0,0 +1,291 @@
+use std::path::Path;
+
+use super::material::Material;
+use super::mesh::{Mesh, Triangle, Vertex};
+use super::vectors::{Color, Point3, Vec3};
+
+/**
+ * Loads a Wavefront OBJ file as a single mesh.
+ * The materials of the MTL file are added to the materials of the scene,
+ * faces without a material use the default material at index 0
+ */
+pub fn load_obj(path: &Path, scene_materials: &mut Vec<Material>) -> Result<Mesh, tobj::LoadError> {
+    let (models, obj_materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+    // a missing or broken mtl file shouldn't stop the mesh from loading
+    let obj_materials = obj_materials.unwrap_or_else(|error| {
+        log::warn!(
+            "could not load the materials of {}: {}",
+            path.display(),
+            error
+        );
+        Vec::new()
+    });
+    let first_material = scene_materials.len() as u32;
+    scene_materials.extend(obj_materials.iter().map(material_from_mtl));
+
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+
+    for model in models {
+        let obj_mesh = model.mesh;
+        let first_vertex = vertices.len() as u32;
+        let material = obj_mesh
+            .material_id
+            .filter(|&id| id < obj_materials.len())
+            .map_or(0, |id| first_material + id as u32);
+
+        vertices.extend((0..obj_mesh.positions.len() / 3).map(|index| {
+            Vertex {
+                position: Point3::new(
+                    obj_mesh.positions[index * 3],
+                    obj_mesh.positions[index * 3 + 1],
+                    obj_mesh.positions[index * 3 + 2],
+                ),
+                normal: obj_mesh
+                    .normals
+                    .get(index * 3..index * 3 + 3)
+                    .map_or(Vec3::default(), |normal| {
+                        Vec3::new(normal[0], normal[1], normal[2])
+                    }),
+                uv: obj_mesh
+                    .texcoords
+                    .get(index * 2..index * 2 + 2)
+                    .map_or([0.0; 2], |uv| [uv[0], uv[1]]),
+            }
+        }));
+
+        triangles.extend(obj_mesh.indices.chunks_exact(3).map(|indices| Triangle {
+            indices: [
+                first_vertex + indices[0],
+                first_vertex + indices[1],
+                first_vertex + indices[2],
+            ],
+            material,
+        }));
+    }
+
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(Mesh::new(name, vertices, triangles))
+}
+
+/**
+ * Maps the Phong style parameters of MTL to our materials.
+ * Emission (Ke) wins over transparency which wins over specular reflection
+ */
+fn material_from_mtl(material: &tobj::Material) -> Material {
+    let color = |value: Option<[f32; 3]>| value.map(Color::from);
+    let brightness = |color: Color| color.x.max(color.y).max(color.z);
+
+    let emission = material
+        .unknown_param
+        .get("Ke")
+        .and_then(|value| parse_color(value));
+    if let Some(emission) = emission.filter(|&emission| brightness(emission) > 0.0) {
+        let strength = brightness(emission);
+        return Material::Emissive {
+            color: emission / strength,
+            strength,
+        };
+    }
+
+    // illumination models 4, 6, 7 and 9 are the ones with refraction
+    let transparent = material.dissolve.is_some_and(|dissolve| dissolve < 1.0)
+        || matches!(material.illumination_model, Some(4 | 6 | 7 | 9));
+    if transparent {
+        return Material::Dielectric {
+            refraction_index: material
+                .optical_density
+                .filter(|&density| density > 0.0)
+                .unwrap_or(1.5),
+        };
+    }
+
+    let diffuse = color(material.diffuse).unwrap_or(Color::new(0.5, 0.5, 0.5));
+    let specular = color(material.specular).unwrap_or_default();
+    // illumination model 3 is "reflection on"
+    let reflective =
+        material.illumination_model == Some(3) || brightness(specular) > brightness(diffuse);
+    if reflective {
+        // the usual conversion from a phong exponent to a roughness
+        let shininess = material.shininess.unwrap_or(0.0).max(0.0);
+        // a reflective material without a specular color reflects with its diffuse color
+        let reflection = if brightness(specular) > 0.0 {
+            specular
+        } else {
+            diffuse
+        };
+        return Material::Metal {
+            albedo: clamp_color(reflection),
+            fuzz: (2.0 / (shininess + 2.0)).sqrt().clamp(0.0, 1.0),
+        };
+    }
+
+    Material::Lambertian {
+        albedo: clamp_color(diffuse),
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let components: Vec<f32> = value
+        .split_whitespace()
+        .map(|component| component.parse().ok())
+        .collect::<Option<_>>()?;
+    match components[..] {
+        [gray] => Some(Color::new(gray, gray, gray)),
+        [r, g, b] => Some(Color::new(r, g, b)),
+        _ => None,
+    }
+}
+
+fn clamp_color(color: Color) -> Color {
+    Color::new(
+        color.x.clamp(0.0, 1.0),
+        color.y.clamp(0.0, 1.0),
+        color.z.clamp(0.0, 1.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::load_obj;
+    use crate::ray_tracer::material::Material;
+    use crate::ray_tracer::vectors::Color;
+
+    const OBJ: &str = "mtllib materials.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+o nothing
+f 1 3 4
+o triangle
+usemtl plastic
+f 1 2 3
+o quad
+usemtl light
+f 1 2 3 4
+";
+
+    const MTL: &str = "newmtl plastic
+Kd 1.5 0.2 0.2
+
+newmtl light
+Kd 1 1 1
+Ke 4 2 0
+d 0.5
+
+newmtl glass
+Ks 1 1 1
+d 0.5
+
+newmtl water
+illum 7
+Ni 1.33
+
+newmtl mirror
+Kd 0.8 0.6 0.2
+illum 3
+Ns 98
+
+newmtl chrome
+Kd 0.1 0.1 0.1
+Ks 0.9 0.9 0.9
+
+newmtl gray
+";
+
+    /// writes the files somewhere only this test uses, tobj reads the mtl file next to the obj file
+    fn with_files<T>(test: &str, files: &[(&str, &str)], read: impl FnOnce(&Path) -> T) -> T {
+        let directory =
+            std::env::temp_dir().join(format!("rt_shader_{}_{}", test, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (name, source) in files {
+            std::fs::write(directory.join(name), source).unwrap();
+        }
+        let result = read(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+        result
+    }
+
+    #[test]
+    fn the_objects_of_a_file_are_one_mesh_with_its_materials_after_the_scene_ones() {
+        let mut materials = vec![Material::default()];
+        let mesh = with_files(
+            "obj",
+            &[("model.obj", OBJ), ("materials.mtl", MTL)],
+            |directory| load_obj(&directory.join("model.obj"), &mut materials).unwrap(),
+        );
+        assert_eq!(mesh.name, "model");
+        assert_eq!(materials.len(), 8);
+        // the quad is split in two triangles and every object has its own vertices
+        assert_eq!(mesh.vertices.len(), 3 + 3 + 4);
+        let triangle_materials: Vec<_> = mesh
+            .triangles
+            .iter()
+            .map(|triangle| triangle.material)
+            .collect();
+        assert_eq!(triangle_materials, [0, 1, 2, 2]);
+        // the indices are the ones of the vertices of their object
+        assert_eq!(mesh.triangles[1].indices.map(|index| index >= 3), [true; 3]);
+        assert_eq!(mesh.triangles[3].indices.map(|index| index >= 6), [true; 3]);
+    }
+
+    #[test]
+    fn mtl_materials_become_the_closest_of_ours() {
+        let mut materials = Vec::new();
+        with_files(
+            "mtl",
+            &[("model.obj", OBJ), ("materials.mtl", MTL)],
+            |directory| load_obj(&directory.join("model.obj"), &mut materials).unwrap(),
+        );
+        assert_eq!(
+            materials,
+            [
+                // the albedo is clamped to what a surface can reflect
+                Material::Lambertian {
+                    albedo: Color::new(1.0, 0.2, 0.2),
+                },
+                // the emission wins over the transparency
+                Material::Emissive {
+                    color: Color::new(1.0, 0.5, 0.0),
+                    strength: 4.0,
+                },
+                // and the transparency over the specular color
+                Material::Dielectric {
+                    refraction_index: 1.5,
+                },
+                Material::Dielectric {
+                    refraction_index: 1.33,
+                },
+                // without a specular color the reflection has the diffuse one
+                Material::Metal {
+                    albedo: Color::new(0.8, 0.6, 0.2),
+                    fuzz: (2.0f32 / 100.0).sqrt(),
+                },
+                Material::Metal {
+                    albedo: Color::new(0.9, 0.9, 0.9),
+                    fuzz: 1.0,
+                },
+                Material::Lambertian {
+                    albedo: Color::new(0.5, 0.5, 0.5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_missing_mtl_file_only_loses_the_materials() {
+        let mut materials = Vec::new();
+        let mesh = with_files("no_mtl", &[("model.obj", OBJ)], |directory| {
+            load_obj(&directory.join("model.obj"), &mut materials).unwrap()
+        });
+        assert!(materials.is_empty());
+        assert!(mesh.triangles.iter().all(|triangle| triangle.material == 0));
+    }
+}
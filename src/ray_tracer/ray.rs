@@ -0,0 +1,28 @@
+use super::transform::Matrix4;
+use super::vectors::{Point3, Vec3};
+
+/**
+ * A ray on the cpu, to find what is under the mouse
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// not always a unit vector, the distances along the ray are in multiples of it
+    pub direction: Vec3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vec3<f32>) -> Self {
+        Ray { origin, direction }
+    }
+
+    /**
+     * The direction isn't normalized so the distances are the same once transformed
+     */
+    pub fn transform(&self, matrix: &Matrix4) -> Self {
+        Ray {
+            origin: matrix.transform_point(self.origin),
+            direction: matrix.transform_vector(self.direction),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::bvh::{Aabb, Bvh};
+use super::camera::Camera;
+use super::material::Material;
+use super::mesh::Mesh;
+use super::ray::Ray;
+use super::transform::Transform;
+use super::validators::at_least_one;
+use super::vectors::{Color, Point3, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+    /// index in the materials of the scene
+    pub material: u32,
+}
+
+impl Sphere {
+    /**
+     * The distance to the closest point where the ray hits the sphere
+     */
+    pub fn hit(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let origin_center = ray.origin - self.center;
+        let a = ray.direction.length_squared();
+        let half_b = origin_center.dot(ray.direction);
+        let c = origin_center.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        [(-half_b - root) / a, (-half_b + root) / a]
+            .into_iter()
+            .find(|&distance| distance > 0.0 && distance < max_distance)
+    }
+}
+
+/**
+ * A copy of a mesh placed in the scene
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instance {
+    /// index in the meshes of the scene
+    pub mesh: u32,
+    pub transform: Transform,
+    /// replaces the materials of the triangles of the mesh
+    pub material: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RenderSettings {
+    /// the maximum number of times a ray can bounce
+    #[serde(deserialize_with = "at_least_one")]
+    pub max_depth: u32,
+    /// one sample is added every frame until every pixel has this many
+    #[serde(deserialize_with = "at_least_one")]
+    pub samples_per_pixel: u32,
+    /// the seed of the random numbers, the same seed always gives the same image.
+    /// Without it a new seed is picked every time the rendering restarts
+    pub seed: Option<u32>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            max_depth: 8,
+            samples_per_pixel: 64,
+            seed: None,
+        }
+    }
+}
+
+/**
+ * Everything the ray tracer can hit. The scene lives on the cpu and gets
+ * uploaded to the gpu whenever it changes
+ */
+#[derive(Clone, Debug)]
+pub struct Scene {
+    pub camera: Camera,
+    /// there is always at least the default material at index 0
+    pub materials: Vec<Material>,
+    pub spheres: Vec<Sphere>,
+    /// the meshes are shared so copying the scene doesn't copy all the triangles
+    pub meshes: Vec<Arc<Mesh>>,
+    pub instances: Vec<Instance>,
+    pub settings: RenderSettings,
+    /// the top level bvh of the spheres and instances, it has to be rebuilt with
+    /// `rebuild_bvh` every time they change
+    pub bvh: Bvh,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            camera: Camera::default(),
+            materials: vec![Material::default()],
+            spheres: Vec::new(),
+            meshes: Vec::new(),
+            instances: Vec::new(),
+            settings: RenderSettings::default(),
+            bvh: Bvh::default(),
+        }
+    }
+}
+
+impl Scene {
+    /**
+     * The scene that used to be hardcoded in the shader with the ground under it
+     */
+    pub fn example() -> Self {
+        let mut scene = Scene {
+            materials: vec![
+                Material::default(),
+                Material::Lambertian {
+                    albedo: Color::new(0.8, 0.8, 0.0),
+                },
+            ],
+            spheres: vec![
+                Sphere {
+                    center: Point3::new(0.0, 0.0, -1.0),
+                    radius: 0.5,
+                    material: 0,
+                },
+                Sphere {
+                    center: Point3::new(0.0, -100.5, -1.0),
+                    radius: 100.0,
+                    material: 1,
+                },
+            ],
+            ..Default::default()
+        };
+        scene.rebuild_bvh();
+        scene
+    }
+
+    /**
+     * The bounds of every primitive of the top level bvh, the spheres first and then the instances.
+     * It's the same order as in the gpu buffers
+     */
+    pub fn primitive_bounds(&self) -> Vec<Aabb> {
+        let spheres = self.spheres.iter().map(|sphere| Aabb {
+            min: sphere.center - Vec3::splat(sphere.radius),
+            max: sphere.center + Vec3::splat(sphere.radius),
+        });
+        let instances = self.instances.iter().map(|instance| {
+            self.meshes[instance.mesh as usize]
+                .bvh
+                .bounds()
+                .transform(&instance.transform.matrix())
+        });
+        spheres.chain(instances).collect()
+    }
+
+    /**
+     * Only rebuilds the top level, the bvh of each mesh is built once when it's created
+     */
+    pub fn rebuild_bvh(&mut self) {
+        self.bvh = Bvh::build(&self.primitive_bounds());
+    }
+
+    /**
+     * Adds a mesh with an instance of it where it was modeled
+     */
+    pub fn add_mesh(&mut self, mesh: Mesh) {
+        self.instances.push(Instance {
+            mesh: self.meshes.len() as u32,
+            transform: Transform::default(),
+            material: None,
+        });
+        self.meshes.push(Arc::new(mesh));
+    }
+
+    /**
+     * Removes a mesh with all its instances
+     */
+    pub fn remove_mesh(&mut self, index: usize) {
+        self.meshes.remove(index);
+        let index = index as u32;
+        self.instances.retain(|instance| instance.mesh != index);
+        for instance in &mut self.instances {
+            if instance.mesh > index {
+                instance.mesh -= 1;
+            }
+        }
+    }
+
+    /**
+     * The distance to the closest thing the ray hits, on the cpu with the same bvh as the gpu
+     */
+    pub fn cast_ray(&self, ray: &Ray) -> Option<f32> {
+        let sphere_count = self.spheres.len() as u32;
+        self.bvh.closest_hit(ray, |primitive, max_distance| {
+            if primitive < sphere_count {
+                return self.spheres[primitive as usize].hit(ray, max_distance);
+            }
+            let instance = &self.instances[(primitive - sphere_count) as usize];
+            let mesh = &self.meshes[instance.mesh as usize];
+            let object_ray = ray.transform(&instance.transform.inverse_matrix());
+            mesh.bvh.closest_hit(&object_ray, |triangle, max_distance| {
+                mesh.hit_triangle(triangle, &object_ray, max_distance)
+            })
+        })
+    }
+}
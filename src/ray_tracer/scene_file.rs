@@ -0,0 +1,508 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::camera::Camera;
+use super::material::Material;
+use super::obj_import::load_obj;
+use super::scene::{Instance, RenderSettings, Scene, Sphere};
+use super::transform::Transform;
+use super::validators::{emission, non_negative, positive};
+use super::vectors::{Color, Point3};
+
+/**
+ * The description of a scene as written in a `.ron` or `.json` file.
+ * Every section is optional except the objects
+ *
+ * ```ron
+ * (
+ *     camera: (position: (0.0, 0.0, 1.0), look_at: (0.0, 0.0, -1.0), vertical_fov: 60.0),
+ *     materials: {
+ *         "ground": Lambertian(albedo: (0.8, 0.8, 0.0)),
+ *         "glass": Dielectric(refraction_index: 1.5),
+ *     },
+ *     objects: [
+ *         Sphere(center: (0.0, -100.5, -1.0), radius: 100.0, material: "ground"),
+ *         Sphere(center: (0.0, 0.0, -1.0), radius: 0.5, material: "glass"),
+ *         Mesh(path: "models/teapot.obj"),
+ *         Mesh(path: "models/teapot.obj", transform: (translation: (2.0, 0.0, 0.0), scale: (0.5, 0.5, 0.5))),
+ *     ],
+ *     lights: [
+ *         Sphere(center: (0.0, 3.0, -1.0), radius: 1.0, color: (1.0, 1.0, 1.0), strength: 4.0),
+ *     ],
+ *     settings: (max_depth: 8, samples_per_pixel: 128),
+ * )
+ * ```
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub camera: Camera,
+    #[serde(default)]
+    pub materials: BTreeMap<String, Material>,
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    #[serde(default)]
+    pub settings: RenderSettings,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ObjectDescription {
+    Sphere {
+        center: Point3<f32>,
+        #[serde(deserialize_with = "positive")]
+        radius: f32,
+        /// the name of one of the materials, the default material is used when there is none
+        #[serde(default)]
+        material: Option<String>,
+    },
+    /// a Wavefront OBJ file, the path is relative to the scene file.
+    /// Every file is only loaded once, using it many times creates instances of the same mesh
+    Mesh {
+        path: PathBuf,
+        /// replaces the materials of the MTL file
+        #[serde(default)]
+        material: Option<String>,
+        #[serde(default)]
+        transform: Transform,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum LightDescription {
+    /// a sphere glowing with the given color
+    Sphere {
+        center: Point3<f32>,
+        #[serde(deserialize_with = "positive")]
+        radius: f32,
+        #[serde(deserialize_with = "emission")]
+        color: Color,
+        #[serde(deserialize_with = "non_negative")]
+        strength: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ron" => Some(SceneFormat::Ron),
+            "json" => Some(SceneFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * An error in a scene file, with as much information as we have on where it is
+ */
+#[derive(Debug)]
+pub struct SceneError {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// the path to the field with the error, like `objects[2].radius`
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl SceneError {
+    fn new(file: &Path, message: impl Into<String>) -> Self {
+        SceneError {
+            file: file.to_path_buf(),
+            line: None,
+            column: None,
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    fn at_field(file: &Path, field: impl Into<String>, message: impl Into<String>) -> Self {
+        SceneError {
+            field: Some(field.into()),
+            ..SceneError::new(file, message)
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": in `{}`", field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/**
+ * Reads, parses and validates the scene file. The format is chosen from the extension
+ */
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let format = SceneFormat::from_path(path).ok_or_else(|| {
+        SceneError::new(path, "unknown scene format, expected a .ron or .json file")
+    })?;
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| SceneError::new(path, format!("could not read the file: {}", error)))?;
+
+    parse_scene_file(&source, format, path)?.into_scene(path)
+}
+
+/**
+ * Parses a scene from its source. The path is only used for error messages
+ */
+pub fn parse_scene_file(
+    source: &str,
+    format: SceneFormat,
+    path: &Path,
+) -> Result<SceneFile, SceneError> {
+    match format {
+        SceneFormat::Ron => {
+            // so optional fields can be written without `Some(...)`
+            let options = ron::Options::default()
+                .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+            let mut deserializer = ron::Deserializer::from_str_with_options(source, options)
+                .map_err(|error| ron_error(path, None, error))?;
+            let scene_file =
+                serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+                    let field = error.path().to_string();
+                    let error = deserializer.span_error(error.into_inner());
+                    ron_error(path, Some(field), error)
+                })?;
+            deserializer
+                .end()
+                .map_err(|error| ron_error(path, None, deserializer.span_error(error)))?;
+            Ok(scene_file)
+        }
+        SceneFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(source);
+            let scene_file =
+                serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+                    let field = error.path().to_string();
+                    json_error(path, Some(field), error.into_inner())
+                })?;
+            deserializer
+                .end()
+                .map_err(|error| json_error(path, None, error))?;
+            Ok(scene_file)
+        }
+    }
+}
+
+fn ron_error(path: &Path, field: Option<String>, error: ron::error::SpannedError) -> SceneError {
+    SceneError {
+        file: path.to_path_buf(),
+        line: Some(error.position.line),
+        column: Some(error.position.col),
+        field: field.filter(|field| field != "."),
+        message: error.code.to_string(),
+    }
+}
+
+fn json_error(path: &Path, field: Option<String>, error: serde_json::Error) -> SceneError {
+    // the error message of serde_json ends with the position, which we already have
+    let message = error.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    };
+    SceneError {
+        file: path.to_path_buf(),
+        line: Some(error.line()),
+        column: Some(error.column()),
+        field: field.filter(|field| field != "."),
+        message,
+    }
+}
+
+impl SceneFile {
+    /**
+     * Resolves the material names and checks what couldn't be checked while parsing
+     */
+    pub fn into_scene(self, path: &Path) -> Result<Scene, SceneError> {
+        let camera_direction = self.camera.look_at - self.camera.position;
+        if camera_direction.length_squared() == 0.0 {
+            return Err(SceneError::at_field(
+                path,
+                "camera.look_at",
+                "the camera can't look at its own position",
+            ));
+        }
+        if camera_direction.cross(self.camera.up).length_squared() == 0.0 {
+            return Err(SceneError::at_field(
+                path,
+                "camera.up",
+                "the up vector can't be parallel to the view direction",
+            ));
+        }
+
+        // the default material is always the first one
+        let mut materials = vec![Material::default()];
+        let mut material_indices = BTreeMap::new();
+        for (name, material) in self.materials {
+            material_indices.insert(name, materials.len() as u32);
+            materials.push(material);
+        }
+
+        let find_material = |index: usize, name: &str| {
+            material_indices.get(name).copied().ok_or_else(|| {
+                SceneError::at_field(
+                    path,
+                    format!("objects[{}].material", index),
+                    format!("there is no material named `{}`", name),
+                )
+            })
+        };
+
+        let mut spheres = Vec::with_capacity(self.objects.len() + self.lights.len());
+        let mut meshes = Vec::new();
+        let mut instances = Vec::new();
+        // the index of each mesh file that was already loaded
+        let mut mesh_indices: HashMap<PathBuf, u32> = HashMap::new();
+        for (index, object) in self.objects.into_iter().enumerate() {
+            match object {
+                ObjectDescription::Sphere {
+                    center,
+                    radius,
+                    material,
+                } => {
+                    let material = match material {
+                        None => 0,
+                        Some(name) => find_material(index, &name)?,
+                    };
+                    spheres.push(Sphere {
+                        center,
+                        radius,
+                        material,
+                    });
+                }
+                ObjectDescription::Mesh {
+                    path: mesh_path,
+                    material,
+                    transform,
+                } => {
+                    let material = match material {
+                        None => None,
+                        Some(name) => Some(find_material(index, &name)?),
+                    };
+                    let mesh_path = path.parent().unwrap_or(Path::new("")).join(mesh_path);
+                    let mesh = match mesh_indices.get(&mesh_path) {
+                        Some(&mesh) => mesh,
+                        None => {
+                            let mesh = load_obj(&mesh_path, &mut materials).map_err(|error| {
+                                SceneError::at_field(
+                                    path,
+                                    format!("objects[{}].path", index),
+                                    format!("could not load {}: {}", mesh_path.display(), error),
+                                )
+                            })?;
+                            meshes.push(Arc::new(mesh));
+                            mesh_indices.insert(mesh_path, meshes.len() as u32 - 1);
+                            meshes.len() as u32 - 1
+                        }
+                    };
+                    instances.push(Instance {
+                        mesh,
+                        transform,
+                        material,
+                    });
+                }
+            }
+        }
+
+        for light in self.lights {
+            match light {
+                LightDescription::Sphere {
+                    center,
+                    radius,
+                    color,
+                    strength,
+                } => {
+                    spheres.push(Sphere {
+                        center,
+                        radius,
+                        material: materials.len() as u32,
+                    });
+                    materials.push(Material::Emissive { color, strength });
+                }
+            }
+        }
+
+        let mut scene = Scene {
+            camera: self.camera,
+            materials,
+            spheres,
+            meshes,
+            instances,
+            settings: self.settings,
+            ..Default::default()
+        };
+        scene.rebuild_bvh();
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse_scene_file, SceneError, SceneFormat};
+
+    fn parse_error(source: &str, format: SceneFormat) -> SceneError {
+        parse_scene_file(source, format, Path::new("scene")).unwrap_err()
+    }
+
+    #[test]
+    fn a_scene_is_read_from_both_formats() {
+        let ron = r#"(
+            materials: {"glass": Dielectric(refraction_index: 1.5)},
+            objects: [Sphere(center: (0.0, 0.0, -1.0), radius: 0.5, material: "glass")],
+            lights: [Sphere(center: (0.0, 3.0, 0.0), radius: 1.0, color: (1.0, 1.0, 1.0), strength: 4.0)],
+            settings: (max_depth: 4),
+        )"#;
+        let json = r#"{
+            "materials": {"glass": {"Dielectric": {"refraction_index": 1.5}}},
+            "objects": [{"Sphere": {"center": [0, 0, -1], "radius": 0.5, "material": "glass"}}],
+            "lights": [{"Sphere": {"center": [0, 3, 0], "radius": 1, "color": [1, 1, 1], "strength": 4}}],
+            "settings": {"max_depth": 4}
+        }"#;
+        for (source, format) in [(ron, SceneFormat::Ron), (json, SceneFormat::Json)] {
+            let scene = parse_scene_file(source, format, Path::new("scene"))
+                .and_then(|scene_file| scene_file.into_scene(Path::new("scene")))
+                .unwrap();
+            // the default material, the glass and the light
+            assert_eq!(scene.materials.len(), 3);
+            assert_eq!(scene.spheres.len(), 2);
+            assert_eq!(scene.spheres[0].material, 1);
+            assert_eq!(scene.settings.max_depth, 4);
+        }
+    }
+
+    #[test]
+    fn a_value_of_the_wrong_type_is_located() {
+        let error = parse_error(
+            "(objects: [\n    Sphere(center: (0.0, 0.0, 0.0), radius: \"big\"),\n])",
+            SceneFormat::Ron,
+        );
+        assert_eq!(error.field.as_deref(), Some("objects[0].Sphere.radius"));
+        assert_eq!((error.line, error.column), (Some(2), Some(45)));
+
+        let error = parse_error(
+            r#"{"objects": [{"Sphere": {"center": [0, 0, 0], "radius": "big"}}]}"#,
+            SceneFormat::Json,
+        );
+        assert_eq!(error.field.as_deref(), Some("objects[0].Sphere.radius"));
+        assert!(
+            error.message.starts_with("invalid type"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn a_missing_field_is_reported() {
+        let error = parse_error("(camera: ())", SceneFormat::Ron);
+        assert!(error.message.contains("`objects`"), "{}", error.message);
+
+        let error = parse_error(
+            r#"{"objects": [{"Sphere": {"center": [0, 0, 0]}}]}"#,
+            SceneFormat::Json,
+        );
+        assert_eq!(error.field.as_deref(), Some("objects[0].Sphere"));
+        assert_eq!(error.message, "missing field `radius`");
+    }
+
+    #[test]
+    fn every_validator_rejects_its_bad_values() {
+        for (source, field, message) in [
+            (
+                "(objects: [Sphere(center: (0.0, 0.0, 0.0), radius: 0.0)])",
+                "objects[0].Sphere.radius",
+                "expected a positive number, got 0",
+            ),
+            (
+                "(objects: [], lights: [Sphere(center: (0.0, 0.0, 0.0), radius: 1.0, color: (1.0, 1.0, 1.0), strength: -1.0)])",
+                "lights[0].Sphere.strength",
+                "expected a number of at least 0, got -1",
+            ),
+            (
+                r#"(materials: {"a": Metal(albedo: (0.5, 0.5, 0.5), fuzz: 2.0)}, objects: [])"#,
+                "materials.a.Metal.fuzz",
+                "expected a number between 0 and 1, got 2",
+            ),
+            (
+                "(camera: (vertical_fov: 180.0), objects: [])",
+                "camera.vertical_fov",
+                "expected a field of view between 0 and 180 degrees, got 180",
+            ),
+            (
+                r#"(materials: {"a": Lambertian(albedo: (1.5, 0.5, 0.5))}, objects: [])"#,
+                "materials.a.Lambertian.albedo",
+                "expected every component of the albedo to be between 0 and 1, got [1.5, 0.5, 0.5]",
+            ),
+            (
+                r#"(materials: {"a": Emissive(color: (1.0, -1.0, 1.0))}, objects: [])"#,
+                "materials.a.Emissive.color",
+                "expected every component of the emitted color to be a number of at least 0, got [1.0, -1.0, 1.0]",
+            ),
+            (
+                "(objects: [], lights: [Sphere(center: (0.0, 0.0, 0.0), radius: 1.0, color: (inf, 1.0, 1.0), strength: 1.0)])",
+                "lights[0].Sphere.color",
+                "expected every component of the emitted color to be a number of at least 0, got [inf, 1.0, 1.0]",
+            ),
+            (
+                "(settings: (samples_per_pixel: 0), objects: [])",
+                "settings.samples_per_pixel",
+                "expected a number of at least 1, got 0",
+            ),
+            (
+                r#"(objects: [Mesh(path: "a.obj", transform: (scale: (1.0, 0.0, 1.0)))])"#,
+                "objects[0].Mesh.transform.scale",
+                "a scale of 0 flattens the object, got [1.0, 0.0, 1.0]",
+            ),
+        ] {
+            let error = parse_error(source, SceneFormat::Ron);
+            assert_eq!(error.field.as_deref(), Some(field), "{}", source);
+            assert_eq!(error.message, message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn an_unknown_material_is_an_error() {
+        let scene_file = parse_scene_file(
+            r#"(objects: [Sphere(center: (0.0, 0.0, 0.0), radius: 1.0, material: "gold")])"#,
+            SceneFormat::Ron,
+            Path::new("scene"),
+        );
+        let error = scene_file
+            .unwrap()
+            .into_scene(Path::new("scene"))
+            .unwrap_err();
+        assert_eq!(error.field.as_deref(), Some("objects[0].material"));
+        assert_eq!(
+            error.to_string(),
+            "scene: in `objects[0].material`: there is no material named `gold`"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::ops::Mul;
+
+use serde::{Deserialize, Serialize};
+
+use super::validators::scale;
+use super::vectors::{Point3, Vec3};
+
+/**
+ * Where an object is placed in the world. The scale is applied first, then the rotation and then the translation
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Transform {
+    pub translation: Vec3<f32>,
+    /// rotations in degrees around the x, then y and then z axis
+    pub rotation: Vec3<f32>,
+    #[serde(deserialize_with = "scale")]
+    pub scale: Vec3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec3::splat(0.0),
+            rotation: Vec3::splat(0.0),
+            scale: Vec3::splat(1.0),
+        }
+    }
+}
+
+impl Transform {
+    /**
+     * The matrix going from object space to world space
+     */
+    pub fn matrix(&self) -> Matrix4 {
+        Matrix4::translation(self.translation) * self.rotation_matrix() * Matrix4::scale(self.scale)
+    }
+
+    /**
+     * The matrix going from world space to object space
+     */
+    pub fn inverse_matrix(&self) -> Matrix4 {
+        let inverse_scale = Vec3::new(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z);
+        // the inverse of a rotation is its transpose
+        Matrix4::scale(inverse_scale)
+            * self.rotation_matrix().transpose()
+            * Matrix4::translation(self.translation * -1.0)
+    }
+
+    fn rotation_matrix(&self) -> Matrix4 {
+        Matrix4::rotation_z(self.rotation.z)
+            * Matrix4::rotation_y(self.rotation.y)
+            * Matrix4::rotation_x(self.rotation.x)
+    }
+}
+
+/**
+ * A 4x4 matrix stored in columns like in wgsl
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub columns: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4 {
+        columns: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn translation(translation: Vec3<f32>) -> Self {
+        let mut matrix = Matrix4::IDENTITY;
+        matrix.columns[3] = [translation.x, translation.y, translation.z, 1.0];
+        matrix
+    }
+
+    pub fn scale(scale: Vec3<f32>) -> Self {
+        let mut matrix = Matrix4::IDENTITY;
+        matrix.columns[0][0] = scale.x;
+        matrix.columns[1][1] = scale.y;
+        matrix.columns[2][2] = scale.z;
+        matrix
+    }
+
+    pub fn rotation_x(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut matrix = Matrix4::IDENTITY;
+        matrix.columns[1] = [0.0, cos, sin, 0.0];
+        matrix.columns[2] = [0.0, -sin, cos, 0.0];
+        matrix
+    }
+
+    pub fn rotation_y(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut matrix = Matrix4::IDENTITY;
+        matrix.columns[0] = [cos, 0.0, -sin, 0.0];
+        matrix.columns[2] = [sin, 0.0, cos, 0.0];
+        matrix
+    }
+
+    pub fn rotation_z(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut matrix = Matrix4::IDENTITY;
+        matrix.columns[0] = [cos, sin, 0.0, 0.0];
+        matrix.columns[1] = [-sin, cos, 0.0, 0.0];
+        matrix
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut matrix = Matrix4::IDENTITY;
+        for column in 0..4 {
+            for row in 0..4 {
+                matrix.columns[column][row] = self.columns[row][column];
+            }
+        }
+        matrix
+    }
+
+    pub fn transform_point(&self, point: Point3<f32>) -> Point3<f32> {
+        self.transform_vector(point)
+            + Vec3::new(self.columns[3][0], self.columns[3][1], self.columns[3][2])
+    }
+
+    /**
+     * Transforms a direction, which isn't affected by the translation
+     */
+    pub fn transform_vector(&self, vector: Vec3<f32>) -> Vec3<f32> {
+        let [x, y, z, _] = self.columns;
+        Vec3::new(x[0], x[1], x[2]) * vector.x
+            + Vec3::new(y[0], y[1], y[2]) * vector.y
+            + Vec3::new(z[0], z[1], z[2]) * vector.z
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Matrix4) -> Self::Output {
+        let mut matrix = Matrix4 {
+            columns: [[0.0; 4]; 4],
+        };
+        for column in 0..4 {
+            for row in 0..4 {
+                matrix.columns[column][row] = (0..4)
+                    .map(|index| self.columns[index][row] * rhs.columns[column][index])
+                    .sum();
+            }
+        }
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Matrix4, Transform};
+    use crate::ray_tracer::vectors::{Point3, Vec3};
+
+    fn transform() -> Transform {
+        Transform {
+            translation: Vec3::new(1.0, 2.0, 3.0),
+            rotation: Vec3::new(0.0, 0.0, 90.0),
+            scale: Vec3::new(2.0, 1.0, 1.0),
+        }
+    }
+
+    fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn the_inverse_matrix_undoes_the_matrix() {
+        let transform = Transform {
+            translation: Vec3::new(-3.0, 0.5, 7.0),
+            rotation: Vec3::new(30.0, -45.0, 120.0),
+            scale: Vec3::new(0.5, 2.0, 3.0),
+        };
+        let product = transform.matrix() * transform.inverse_matrix();
+        for column in 0..4 {
+            for row in 0..4 {
+                let expected = Matrix4::IDENTITY.columns[column][row];
+                assert!(
+                    (product.columns[column][row] - expected).abs() < 1e-5,
+                    "{:?}",
+                    product
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn points_are_scaled_then_rotated_then_translated() {
+        let matrix = transform().matrix();
+        // (1, 0, 0) is scaled to (2, 0, 0) and turned to (0, 2, 0)
+        assert_close(
+            matrix.transform_point(Point3::new(1.0, 0.0, 0.0)),
+            Point3::new(1.0, 4.0, 3.0),
+        );
+        assert_close(
+            transform()
+                .inverse_matrix()
+                .transform_point(Point3::new(1.0, 4.0, 3.0)),
+            Point3::new(1.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn vectors_are_not_translated() {
+        let matrix = transform().matrix();
+        assert_close(
+            matrix.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 2.0, 0.0),
+        );
+        assert_close(
+            matrix.transform_vector(Vec3::new(0.0, 0.0, 1.0)),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Checks of the values of the scene done while deserializing it, used with `#[serde(deserialize_with = "...")]`
+//! so that the error of a scene file has the line of the value
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use super::vectors::{Color, Vec3};
+
+pub fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected a positive number, got {}",
+            value
+        )))
+    }
+}
+
+pub fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected a number of at least 0, got {}",
+            value
+        )))
+    }
+}
+
+pub fn unit_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected a number between 0 and 1, got {}",
+            value
+        )))
+    }
+}
+
+pub fn field_of_view<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if value > 0.0 && value < 180.0 {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected a field of view between 0 and 180 degrees, got {}",
+            value
+        )))
+    }
+}
+
+pub fn albedo<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let color = Color::deserialize(deserializer)?;
+    let components = [color.x, color.y, color.z];
+    if components
+        .iter()
+        .all(|component| (0.0..=1.0).contains(component))
+    {
+        Ok(color)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected every component of the albedo to be between 0 and 1, got {:?}",
+            components
+        )))
+    }
+}
+
+pub fn emission<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let color = Color::deserialize(deserializer)?;
+    let components = [color.x, color.y, color.z];
+    if components
+        .iter()
+        .all(|component| component.is_finite() && *component >= 0.0)
+    {
+        Ok(color)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected every component of the emitted color to be a number of at least 0, got {:?}",
+            components
+        )))
+    }
+}
+
+pub fn at_least_one<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let value = u32::deserialize(deserializer)?;
+    if value >= 1 {
+        Ok(value)
+    } else {
+        Err(D::Error::custom("expected a number of at least 1, got 0"))
+    }
+}
+
+pub fn scale<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3<f32>, D::Error> {
+    let scale = Vec3::<f32>::deserialize(deserializer)?;
+    if scale.x != 0.0 && scale.y != 0.0 && scale.z != 0.0 {
+        Ok(scale)
+    } else {
+        Err(D::Error::custom(format!(
+            "a scale of 0 flattens the object, got {:?}",
+            <[f32; 3]>::from(scale)
+        )))
+    }
+}
@@ -1,12 +1,37 @@
-use std::ops::{Add, Div, Mul, Sub};
-
+use std::ops::{Add, Div, Index, Mul, Sub};
+
+use serde::{Deserialize, Serialize};
+
+// in scene files a vector is written as a tuple (x, y, z) instead of a struct
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(
+    from = "[T; 3]",
+    into = "[T; 3]",
+    bound(
+        serialize = "T: Copy + Serialize",
+        deserialize = "T: Copy + Deserialize<'de>"
+    )
+)]
 pub struct Vec3<T> {
     pub x: T,
     pub y: T,
     pub z: T,
 }
 
-type Point3<T> = Vec3<T>;
+pub type Point3<T> = Vec3<T>;
+pub type Color = Vec3<f32>;
+
+impl<T: Copy> From<[T; 3]> for Vec3<T> {
+    fn from([x, y, z]: [T; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl<T: Copy> From<Vec3<T>> for [T; 3] {
+    fn from(vector: Vec3<T>) -> Self {
+        [vector.x, vector.y, vector.z]
+    }
+}
 
 impl<T> Vec3<T>
 where
@@ -47,6 +72,38 @@ impl Vec3<f32> {
         let division = 1.0 / self.length();
         self * division
     }
+
+    #[inline]
+    pub fn min(self, v: Self) -> Self {
+        Self::new(self.x.min(v.x), self.y.min(v.y), self.z.min(v.z))
+    }
+
+    #[inline]
+    pub fn max(self, v: Self) -> Self {
+        Self::new(self.x.max(v.x), self.y.max(v.y), self.z.max(v.z))
+    }
+
+    #[inline]
+    pub fn splat(value: f32) -> Self {
+        Self::new(value, value, value)
+    }
+}
+
+impl<T> Index<usize> for Vec3<T> {
+    type Output = T;
+
+    /**
+     * 0 is x, 1 is y and 2 is z
+     */
+    #[inline]
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("a Vec3 only has 3 axes, tried to get axis {}", axis),
+        }
+    }
 }
 
 impl<T> Add for Vec3<T>
@@ -0,0 +1,35 @@
+// the rays going from the camera through the pixels
+
+// a ray through a random point of the pixel, from a random point of the lens
+fn get_camera_ray(screen_position: vec2u, rng_state: ptr<function, u32>) -> Ray {
+    let image_width = shared_stage_uniform.size.x;
+    let image_height = shared_stage_uniform.size.y;
+
+    let aspect_ratio = image_width / image_height;
+
+
+    // the viewport is on the plane in focus so the rays from every point of the lens meet there
+    let viewport_height = 2.0 * camera.viewport_half_height * camera.focus_distance;
+    let viewport_width = viewport_height * aspect_ratio;
+
+    let camera_center = camera.position;
+
+    let viewport_u = viewport_width * camera.right;
+    let viewport_v = -viewport_height * camera.up;
+    let pixel_delta_u = viewport_u / f32(image_width);
+    let pixel_delta_v = viewport_v / f32(image_height);
+
+    let viewport_upper_left = camera_center + camera.focus_distance * camera.forward - (viewport_u / 2.0) - (viewport_v / 2.0);
+    let pixel_00 = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+
+    // a random point in the pixel so the samples average out the edges (anti-aliasing)
+    let jitter = vec2f(random_f32(rng_state), random_f32(rng_state)) - 0.5;
+    let pixel_center = pixel_00 + (f32(screen_position.x) + jitter.x) * pixel_delta_u + (f32(screen_position.y) + jitter.y) * pixel_delta_v;
+
+    // a random point on the lens blurs what isn't on the focus plane
+    let lens_point = camera.lens_radius * random_in_unit_disk(rng_state);
+    let ray_origin = camera_center + lens_point.x * camera.right + lens_point.y * camera.up;
+    let ray_direction = pixel_center - ray_origin;
+
+    return Ray(ray_origin, ray_direction);
+}
@@ -0,0 +1,61 @@
+// how the radiance of the image is turned into colors for the display
+
+// the order of Tonemapper::ALL
+const TONEMAPPER_CLAMP = 0u;
+const TONEMAPPER_REINHARD = 1u;
+const TONEMAPPER_ACES = 2u;
+const TONEMAPPER_AGX = 3u;
+
+fn tonemap(color: vec3f) -> vec3f {
+    let tonemapper = display.tonemapper;
+    if(tonemapper == TONEMAPPER_REINHARD) {
+        return color / (1.0 + color);
+    } else if(tonemapper == TONEMAPPER_ACES) {
+        return aces(color);
+    } else if(tonemapper == TONEMAPPER_AGX) {
+        return agx(color);
+    }
+    return clamp(color, vec3f(0.0), vec3f(1.0));
+}
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(color: vec3f) -> vec3f {
+    let numerator = color * (2.51 * color + 0.03);
+    let denominator = color * (2.43 * color + 0.59) + 0.14;
+    return clamp(numerator / denominator, vec3f(0.0), vec3f(1.0));
+}
+
+// the minimal version of AgX by Benjamin Wrensch, https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx(color: vec3f) -> vec3f {
+    let inset = mat3x3f(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3f(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    // the log of 0 is infinite
+    var encoded = clamp(log2(max(inset * color, vec3f(1e-10))), vec3f(min_ev), vec3f(max_ev));
+    encoded = agx_contrast((encoded - min_ev) / (max_ev - min_ev));
+    // the curve gives colors for a display with a gamma of 2.2, they're made linear again
+    return pow(max(outset * encoded, vec3f(0.0)), vec3f(2.2));
+}
+
+// a polynomial fitted to the sigmoid of the default look
+fn agx_contrast(x: vec3f) -> vec3f {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn linear_to_srgb(color: vec3f) -> vec3f {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3f(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3f(0.0031308));
+}
@@ -0,0 +1,56 @@
+// how the rays bounce on the materials
+
+// the kinds of materials, the tests check them against the ones of shared_stage_data
+const LAMBERTIAN = 0u;
+const METAL = 1u;
+const DIELECTRIC = 2u;
+const EMISSIVE = 3u;
+
+// gives the direction the ray bounces in, or false when the material absorbs it
+fn scatter(material: Material, ray: Ray, hit_record: HitRecord, rng_state: ptr<function, u32>, scattered: ptr<function, Ray>) -> bool {
+    let normal = hit_record.normal;
+    if(material.kind == LAMBERTIAN) {
+        var direction = normal + random_unit_vector(rng_state);
+        // the random vector can be almost opposite to the normal
+        if(length_squared(direction) < 1e-8) {
+            direction = normal;
+        }
+        *scattered = Ray(hit_record.point, direction);
+        return true;
+    }
+    if(material.kind == METAL) {
+        let reflected = reflect(normalize(ray.direction), normal);
+        let direction = reflected + material.parameter * random_unit_vector(rng_state);
+        *scattered = Ray(hit_record.point, direction);
+        // the fuzz can send the ray under the surface
+        return dot(direction, normal) > 0.0;
+    }
+    if(material.kind == DIELECTRIC) {
+        var ratio = material.parameter;
+        if(hit_record.front_face) {
+            ratio = 1.0 / material.parameter;
+        }
+        let unit_direction = normalize(ray.direction);
+        let cos_theta = min(dot(-unit_direction, normal), 1.0);
+        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        // total internal reflection happens when snell's law has no solution
+        let cannot_refract = ratio * sin_theta > 1.0;
+        var direction: vec3f;
+        if(cannot_refract || reflectance(cos_theta, ratio) > random_f32(rng_state)) {
+            direction = reflect(unit_direction, normal);
+        } else {
+            direction = refract(unit_direction, normal, ratio);
+        }
+        *scattered = Ray(hit_record.point, direction);
+        return true;
+    }
+    // emissive materials only give off light
+    return false;
+}
+
+// Schlick's approximation of how much light is reflected by glass
+fn reflectance(cosine: f32, ratio: f32) -> f32 {
+    var r0 = (1.0 - ratio) / (1.0 + ratio);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
@@ -0,0 +1,33 @@
+// rays and what they hit
+
+struct Ray {
+    origin: vec3f,
+    direction: vec3f,
+}
+
+fn length_squared(vector: vec3f) -> f32 {
+    return vector.x * vector.x + vector.y * vector.y + vector.z * vector.z;
+}
+
+fn ray_at_distance(ray: Ray, t: f32) -> vec3f {
+    return ray.origin + t * ray.direction;
+}
+
+// hitting things
+struct HitRecord {
+    point: vec3f,
+    // always against the ray, front_face says if it was flipped
+    normal: vec3f,
+    distance_from_ray: f32,
+    material: u32,
+    // true when the ray comes from outside of the object
+    front_face: bool,
+}
+
+// the primitives give the normal pointing out of the object, this turns it against the ray
+fn set_face_normal(hit_record: ptr<function, HitRecord>, ray: Ray) {
+    (*hit_record).front_face = dot(ray.direction, (*hit_record).normal) < 0.0;
+    if(!(*hit_record).front_face) {
+        (*hit_record).normal = -(*hit_record).normal;
+    }
+}
@@ -1,128 +1,104 @@
-@group(0) @binding(0)
-var output_color: texture_storage_2d<rgba8unorm, write>;
+// the structs of the buffers are declared from the rust ones in src/gpu, after this code
+// the includes are replaced by the code of the modules, compute_stage.rs chooses the defines for every pipeline
+//!include shared_stage.wgsl ray.wgsl rng.wgsl sampling.wgsl camera.wgsl shapes.wgsl materials.wgsl
 
-struct SharedStageUniform {
-    size: vec2f,
-}
+// the size of the workgroups, the image is covered by as many as needed
+//!define WORKGROUP_WIDTH 16
+//!define WORKGROUP_HEIGHT 16
 
-@group(1) @binding(0)
-var<uniform> shared_stage_uniform: SharedStageUniform;
+@group(0) @binding(0)
+var output_color: texture_storage_2d<rgba16float, write>;
 
-@compute
-@workgroup_size(16,16,1)
-fn compute_main(@builtin(global_invocation_id) compute_id: vec3u) {
-    let screen_position = compute_id.xy;
-    let dimentions = textureDimensions(output_color);
-    // this is commented out for debugging
-    // textureStore(output_color, screen_position, vec4f((vec2f(screen_position) / vec2f(dimentions)), 0.0, 1.0));
-    textureStore(output_color, screen_position, get_pixel_color(screen_position));
-}
+// the sum of the samples of the previous frames
+@group(0) @binding(1)
+var previous_accumulation: texture_2d<f32>;
 
+// the sum with the sample of this frame, it's the previous accumulation of the next frame
+@group(0) @binding(2)
+var next_accumulation: texture_storage_2d<rgba32float, write>;
 
-fn get_pixel_color(screen_position: vec2u) -> vec4f {
+// the uniform at binding 0 is in shared_stage.wgsl
+@group(1) @binding(1)
+var<storage, read> spheres: array<Sphere>;
 
-    // the camera stuff
-    let image_width = shared_stage_uniform.size.x;
-    let image_height = shared_stage_uniform.size.y;
+@group(1) @binding(2)
+var<storage, read> vertices: array<Vertex>;
 
-    let aspect_ratio = image_width / image_height;
+@group(1) @binding(3)
+var<storage, read> triangles: array<Triangle>;
 
+@group(1) @binding(4)
+var<storage, read> instances: array<Instance>;
 
-    let viewport_height = 2.0;
-    let viewport_width = viewport_height * aspect_ratio;
+// the bvh of every mesh and then the top level bvh
+@group(1) @binding(5)
+var<storage, read> bvh_nodes: array<BvhNode>;
 
-    let camera_center = vec3f();
-    let focal_length = 1.0;
+// the triangles for the bvh of the meshes,
+// the spheres and then the instances for the top level bvh
+@group(1) @binding(6)
+var<storage, read> primitive_indices: array<u32>;
 
-    let viewport_u = vec3f(viewport_width, 0.0, 0.0);
-    let viewport_v = vec3f(0.0, -viewport_height, 0.0);
-    let pixel_delta_u = viewport_u / f32(image_width);
-    let pixel_delta_v = viewport_v / f32(image_height);
+@group(1) @binding(7)
+var<storage, read> materials: array<Material>;
 
-    let viewport_upper_left = camera_center - vec3f(0.0, 0.0, focal_length) - (viewport_u / 2.0) - (viewport_v / 2.0);
-    let pixel_00 = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+@group(1) @binding(8)
+var<uniform> camera: Camera;
 
-    let pixel_center = pixel_00 + (f32(screen_position.x) * pixel_delta_u) + (f32(screen_position.y) * pixel_delta_v);
+@compute
+@workgroup_size(WORKGROUP_WIDTH, WORKGROUP_HEIGHT, 1)
+fn compute_main(@builtin(global_invocation_id) compute_id: vec3u) {
+    let screen_position = compute_id.xy;
+    // the last workgroups go past the edges when the size isn't a multiple of the workgroup size
+    if(any(vec2f(screen_position) >= shared_stage_uniform.size)) {
+        return;
+    }
+    let dimentions = textureDimensions(output_color);
+    // this is commented out for debugging
+    // textureStore(output_color, screen_position, vec4f((vec2f(screen_position) / vec2f(dimentions)), 0.0, 1.0));
+    var sum = get_pixel_color(screen_position);
+    if(shared_stage_uniform.sample_count > 0u) {
+        sum += textureLoad(previous_accumulation, screen_position, 0);
+    }
+    textureStore(next_accumulation, screen_position, sum);
+    textureStore(output_color, screen_position, sum / f32(shared_stage_uniform.sample_count + 1u));
+}
 
-    let ray_direction = pixel_center - camera_center;
 
-    let ray = Ray(camera_center, ray_direction);
-    return vec4f(get_ray_color(ray), 1.0);
+fn get_pixel_color(screen_position: vec2u) -> vec4f {
+    var rng_state = rng_seed(screen_position, shared_stage_uniform.sample_count, shared_stage_uniform.seed);
+    let ray = get_camera_ray(screen_position, &rng_state);
+    return vec4f(get_ray_color(ray, &rng_state), 1.0);
 }
 
-const main_sphere = Sphere(0.5, vec3f(0.0, 0.0, -1.0));
-
-fn get_ray_color(ray: Ray) -> vec3f {
-    var hit_record: HitRecord;
-    if(hit_sphere(main_sphere, ray, 0.0, 9999.0, &hit_record)) {
-        return 0.5 * (hit_record.normal + 1.0);
+// follows the ray as it bounces around the scene, adding the light of what it hits along the way
+fn get_ray_color(first_ray: Ray, rng_state: ptr<function, u32>) -> vec3f {
+    var ray = first_ray;
+    // how much of the light is left after the previous bounces
+    var attenuation = vec3f(1.0);
+    var color = vec3f();
+    for(var depth = 0u; depth < shared_stage_uniform.max_depth; depth++) {
+        var hit_record: HitRecord;
+        // a bit more than 0 so the ray doesn't hit the surface it's leaving because of rounding errors
+        if(!hit_scene(ray, 0.001, INFINITY, &hit_record)) {
+            color += attenuation * sky_color(ray);
+            break;
+        }
+
+        let material = materials[hit_record.material];
+        color += attenuation * material.emission;
+        var scattered: Ray;
+        if(!scatter(material, ray, hit_record, rng_state, &scattered)) {
+            break;
+        }
+        attenuation *= material.albedo;
+        ray = scattered;
     }
+    return color;
+}
 
+fn sky_color(ray: Ray) -> vec3f {
     let unit = normalize(ray.direction);
     let a = 0.5 * (unit.y + 1.0);
     return vec3f((1.0 - a) * vec3f(1.0) + a * vec3f(0.5, 0.7, 1.0));
 }
-
-
-
-
-// Ray part of the code
-
-struct Ray {
-    origin: vec3f,
-    direction: vec3f,
-}
-
-fn length_squared(vector: vec3f) -> f32 {
-    return vector.x * vector.x + vector.y * vector.y + vector.z * vector.z;
-}
-
-fn ray_at_distance(ray: Ray, t: f32) -> vec3f {
-    return ray.origin + t * ray.direction;
-}
-
-// hitting things
-struct HitRecord {
-    point: vec3f,
-    normal: vec3f,
-    distance_from_ray: f32,
-}
-
-
-// Sphere part of the code
-struct Sphere {
-    radius: f32,
-    center: vec3f,
-}
-
-fn hit_sphere(sphere: Sphere, ray: Ray, min_distance: f32, max_distance: f32, hit_record: ptr<function ,HitRecord>) -> bool {
-    let origin_center = ray.origin - sphere.center;
-    let a = length_squared(ray.direction);
-    let half_b = dot(origin_center, ray.direction);
-    let c = length_squared(origin_center) - sphere.radius * sphere.radius;
-    let discriminant = half_b*half_b - a * c;
-    if(discriminant < 0.0) {
-        return false;
-    }
-    let root = sqrt(discriminant);
-    let root1 =  (-half_b - root) / a;
-    let root2 = (-half_b + root) / a;
-    let out_of_bounds1 = max_distance <= root1 || root1 <= min_distance;
-    let out_of_bounds2 = max_distance <= root2 || root2 <= min_distance;
-    if(out_of_bounds1 && out_of_bounds2) {
-        return false;
-    }
-    let distance = min(root1, root2);
-    let point = ray_at_distance(ray, distance);
-    (*hit_record).distance_from_ray = distance;
-    (*hit_record).point = point;
-    (*hit_record).normal = normalize(point - sphere.center);
-
-    return true;
-}
-
-// point is the point along the surface of the sphere that hit the sphere
-// center is the center of the sphere
-fn sphere_point_normal(point: vec3f, center: vec3f) -> vec3f {
-    return normalize(point - center);
-}
\ No newline at end of file
@@ -1,20 +1,23 @@
+// the structs of the uniforms are declared from the rust ones in src/gpu, after this code
+// the includes are replaced by the code of the modules, render_stage.rs chooses the defines from the target format
+//!include shared_stage.wgsl color.wgsl
+
+// encodes the colors in srgb, for the targets that don't do it when they're written
+//!define ENCODE_SRGB true
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) texture_coordinates: vec2f,
 }
 
-struct SharedStageUniform {
-    size: vec2f,
-}
-
 @group(0) @binding(0)
 var texture_to_render: texture_2d<f32>;
 
 @group(0) @binding(1)
 var texture_sampler: sampler;
 
-@group(1) @binding(0)
-var<uniform> shared_stage_uniform: SharedStageUniform;
+@group(0) @binding(2)
+var<uniform> display: Display;
 
 // This has to be outside the function or it shit's it's pants
 var<private> vertex_positions: array<vec2f, 6> =  array<vec2f, 6>(
@@ -63,5 +66,19 @@ fn vertex_main(@builtin(vertex_index) index: u32) -> VertexOutput {
 
 @fragment
 fn fragment_main(input: VertexOutput) -> @location(0) vec4f {
-    return textureSample(texture_to_render, texture_sampler, input.texture_coordinates);
-}
\ No newline at end of file
+    // the texture can be bigger than the image, so the interpolation shouldn't go
+    // further than the center of the last pixel or it would mix in what is after the image
+    let texel_size = 1.0 / vec2f(textureDimensions(texture_to_render));
+    let texture_coordinates = clamp(
+        input.texture_coordinates,
+        0.5 * texel_size,
+        (shared_stage_uniform.size - 0.5) * texel_size,
+    );
+    let radiance = textureSample(texture_to_render, texture_sampler, texture_coordinates).rgb;
+    var color = tonemap(radiance * exp2(display.exposure));
+    // defined when the pipeline is created, a srgb target encodes the colors itself
+    if(ENCODE_SRGB) {
+        color = linear_to_srgb(color);
+    }
+    return vec4f(color, 1.0);
+}
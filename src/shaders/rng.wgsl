@@ -0,0 +1,21 @@
+// the random numbers of the samples
+
+// the pcg hash from "Hash Functions for GPU Rendering" (Jarzynski and Olano, 2020)
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// every pixel of every frame needs different random numbers or the samples would all be the same,
+// hashing each part separately keeps nearby pixels and frames from having related states
+fn rng_seed(pixel: vec2u, frame: u32, seed: u32) -> u32 {
+    return pcg_hash(pixel.x + pcg_hash(pixel.y + pcg_hash(frame + pcg_hash(seed))));
+}
+
+// a number between 0 and 1 (excluded), moving the state forward
+fn random_f32(rng_state: ptr<function, u32>) -> f32 {
+    *rng_state = pcg_hash(*rng_state);
+    // only 24 bits fit exactly in a f32
+    return f32(*rng_state >> 8u) / 16777216.0;
+}
@@ -0,0 +1,18 @@
+// random points in shapes, from the random numbers of rng.wgsl
+const PI = 3.14159265358979;
+
+// a random point on the sphere of radius 1
+fn random_unit_vector(rng_state: ptr<function, u32>) -> vec3f {
+    let z = 2.0 * random_f32(rng_state) - 1.0;
+    let angle = 2.0 * PI * random_f32(rng_state);
+    let radius = sqrt(1.0 - z * z);
+    return vec3f(radius * cos(angle), radius * sin(angle), z);
+}
+
+// a random point in the disk of radius 1
+fn random_in_unit_disk(rng_state: ptr<function, u32>) -> vec2f {
+    // the square root spreads the points evenly instead of packing them in the center
+    let radius = sqrt(random_f32(rng_state));
+    let angle = 2.0 * PI * random_f32(rng_state);
+    return radius * vec2f(cos(angle), sin(angle));
+}
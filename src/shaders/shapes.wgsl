@@ -0,0 +1,269 @@
+// the shapes of the scene and the bvh going through them
+
+// finds the closest thing the ray hits in the scene by going through the top level bvh
+fn hit_scene(ray: Ray, min_distance: f32, max_distance: f32, hit_record: ptr<function, HitRecord>) -> bool {
+    if(shared_stage_uniform.tlas_root == NO_NODE) {
+        return false;
+    }
+    var closest_distance = max_distance;
+    var hit_anything = false;
+    var temp_record: HitRecord;
+    let inverse_direction = 1.0 / ray.direction;
+
+    // the nodes left to visit
+    var stack: array<u32, MAX_BVH_DEPTH>;
+    var stack_size = 1u;
+    stack[0] = shared_stage_uniform.tlas_root;
+    while(stack_size > 0u) {
+        stack_size--;
+        let node = bvh_nodes[stack[stack_size]];
+        if(hit_aabb(node.min, node.max, ray, inverse_direction, min_distance, closest_distance) == INFINITY) {
+            continue;
+        }
+
+        if(node.primitive_count > 0u) {
+            for(var i = node.left_or_first; i < node.left_or_first + node.primitive_count; i++) {
+                if(hit_primitive(primitive_indices[i], ray, min_distance, closest_distance, &temp_record)) {
+                    hit_anything = true;
+                    closest_distance = temp_record.distance_from_ray;
+                    *hit_record = temp_record;
+                }
+            }
+            continue;
+        }
+        push_children(node, ray, inverse_direction, min_distance, closest_distance, &stack, &stack_size);
+    }
+    if(hit_anything) {
+        set_face_normal(hit_record, ray);
+    }
+    return hit_anything;
+}
+
+fn hit_primitive(primitive: u32, ray: Ray, min_distance: f32, max_distance: f32, hit_record: ptr<function, HitRecord>) -> bool {
+    let sphere_count = shared_stage_uniform.sphere_count;
+    if(primitive < sphere_count) {
+        return hit_sphere(spheres[primitive], ray, min_distance, max_distance, hit_record);
+    }
+    return hit_instance(instances[primitive - sphere_count], ray, min_distance, max_distance, hit_record);
+}
+
+// pushes the children of the node that the ray hits on the stack,
+// the closest one last so it's visited first and the other one can be skipped when something closer is hit
+fn push_children(node: BvhNode, ray: Ray, inverse_direction: vec3f, min_distance: f32, max_distance: f32, stack: ptr<function, array<u32, MAX_BVH_DEPTH>>, stack_size: ptr<function, u32>) {
+    let left = bvh_nodes[node.left_or_first];
+    let right = bvh_nodes[node.left_or_first + 1u];
+    let left_distance = hit_aabb(left.min, left.max, ray, inverse_direction, min_distance, max_distance);
+    let right_distance = hit_aabb(right.min, right.max, ray, inverse_direction, min_distance, max_distance);
+    var near = node.left_or_first;
+    var far = node.left_or_first + 1u;
+    var near_distance = left_distance;
+    var far_distance = right_distance;
+    if(right_distance < left_distance) {
+        near = far;
+        far = node.left_or_first;
+        near_distance = right_distance;
+        far_distance = left_distance;
+    }
+    if(far_distance != INFINITY) {
+        (*stack)[*stack_size] = far;
+        (*stack_size)++;
+    }
+    if(near_distance != INFINITY) {
+        (*stack)[*stack_size] = near;
+        (*stack_size)++;
+    }
+}
+
+
+// Instance part of the code
+// the tests check it against shared_stage_data::NO_MATERIAL
+const NO_MATERIAL = 0xffffffffu;
+
+// moves the ray in the space of the mesh and goes through the bvh of the mesh
+fn hit_instance(instance: Instance, world_ray: Ray, min_distance: f32, max_distance: f32, hit_record: ptr<function, HitRecord>) -> bool {
+    if(instance.blas_root == NO_NODE) {
+        return false;
+    }
+    // the direction isn't normalized so the distances are the same in both spaces
+    let ray = Ray(
+        (instance.world_to_object * vec4f(world_ray.origin, 1.0)).xyz,
+        (instance.world_to_object * vec4f(world_ray.direction, 0.0)).xyz,
+    );
+    var closest_distance = max_distance;
+    var hit_anything = false;
+    var temp_record: HitRecord;
+    let inverse_direction = 1.0 / ray.direction;
+
+    var stack: array<u32, MAX_BVH_DEPTH>;
+    var stack_size = 1u;
+    stack[0] = instance.blas_root;
+    while(stack_size > 0u) {
+        stack_size--;
+        let node = bvh_nodes[stack[stack_size]];
+        if(hit_aabb(node.min, node.max, ray, inverse_direction, min_distance, closest_distance) == INFINITY) {
+            continue;
+        }
+
+        if(node.primitive_count > 0u) {
+            for(var i = node.left_or_first; i < node.left_or_first + node.primitive_count; i++) {
+                if(hit_triangle(triangles[primitive_indices[i]], ray, min_distance, closest_distance, &temp_record)) {
+                    hit_anything = true;
+                    closest_distance = temp_record.distance_from_ray;
+                    *hit_record = temp_record;
+                }
+            }
+            continue;
+        }
+        push_children(node, ray, inverse_direction, min_distance, closest_distance, &stack, &stack_size);
+    }
+
+    if(hit_anything) {
+        // normals are moved back with the transpose of the inverse of the transform
+        let to_object = instance.world_to_object;
+        let normal_matrix = transpose(mat3x3f(to_object[0].xyz, to_object[1].xyz, to_object[2].xyz));
+        (*hit_record).point = ray_at_distance(world_ray, closest_distance);
+        (*hit_record).normal = normalize(normal_matrix * (*hit_record).normal);
+        if(instance.material != NO_MATERIAL) {
+            (*hit_record).material = instance.material;
+        }
+    }
+    return hit_anything;
+}
+
+
+// BVH part of the code
+// the depth of the trees built by the cpu, the tests check it against bvh::MAX_BVH_DEPTH
+const MAX_BVH_DEPTH = 64;
+const INFINITY = 3.40282347e38; // wgsl doesn't have infinity, so it's the biggest f32
+// the tests check it against shared_stage_data::NO_NODE
+const NO_NODE = 0xffffffffu;
+
+// the slab test, gives the distance where the ray enters the box or INFINITY when it misses
+fn hit_aabb(box_min: vec3f, box_max: vec3f, ray: Ray, inverse_direction: vec3f, min_distance: f32, max_distance: f32) -> f32 {
+    let to_min = (box_min - ray.origin) * inverse_direction;
+    let to_max = (box_max - ray.origin) * inverse_direction;
+    let near = min(to_min, to_max);
+    let far = max(to_min, to_max);
+    let enter = max(max(near.x, near.y), max(near.z, min_distance));
+    let exit = min(min(far.x, far.y), min(far.z, max_distance));
+    if(enter > exit) {
+        return INFINITY;
+    }
+    return enter;
+}
+
+
+// Sphere part of the code
+fn hit_sphere(sphere: Sphere, ray: Ray, min_distance: f32, max_distance: f32, hit_record: ptr<function ,HitRecord>) -> bool {
+    let origin_center = ray.origin - sphere.center;
+    let a = length_squared(ray.direction);
+    let half_b = dot(origin_center, ray.direction);
+    let c = length_squared(origin_center) - sphere.radius * sphere.radius;
+    let discriminant = half_b*half_b - a * c;
+    if(discriminant < 0.0) {
+        return false;
+    }
+    let root = sqrt(discriminant);
+    let root1 =  (-half_b - root) / a;
+    let root2 = (-half_b + root) / a;
+    let out_of_bounds1 = max_distance <= root1 || root1 <= min_distance;
+    let out_of_bounds2 = max_distance <= root2 || root2 <= min_distance;
+    if(out_of_bounds1 && out_of_bounds2) {
+        return false;
+    }
+    // the closest root that is in bounds
+    var distance = root1;
+    if(out_of_bounds1) {
+        distance = root2;
+    }
+    let point = ray_at_distance(ray, distance);
+    (*hit_record).distance_from_ray = distance;
+    (*hit_record).point = point;
+    (*hit_record).normal = normalize(point - sphere.center);
+    (*hit_record).material = sphere.material;
+
+    return true;
+}
+
+// point is the point along the surface of the sphere that hit the sphere
+// center is the center of the sphere
+fn sphere_point_normal(point: vec3f, center: vec3f) -> vec3f {
+    return normalize(point - center);
+}
+
+
+// Triangle part of the code
+// watertight ray triangle intersection from Woop, Benthin and Wald (2013)
+// rays going exactly through an edge or a vertex shared by two triangles always hit one of them
+fn hit_triangle(triangle: Triangle, ray: Ray, min_distance: f32, max_distance: f32, hit_record: ptr<function, HitRecord>) -> bool {
+    let vertex0 = vertices[triangle.indices.x];
+    let vertex1 = vertices[triangle.indices.y];
+    let vertex2 = vertices[triangle.indices.z];
+
+    // the axis where the direction is the biggest becomes z
+    let direction_size = abs(ray.direction);
+    var kz = 2u;
+    if(direction_size.x > direction_size.y && direction_size.x > direction_size.z) {
+        kz = 0u;
+    } else if(direction_size.y > direction_size.z) {
+        kz = 1u;
+    }
+    var kx = (kz + 1u) % 3u;
+    var ky = (kx + 1u) % 3u;
+    // swap to keep the winding of the triangle
+    if(ray.direction[kz] < 0.0) {
+        let swap = kx;
+        kx = ky;
+        ky = swap;
+    }
+
+    // shear so that the ray goes along z
+    let shear_z = 1.0 / ray.direction[kz];
+    let shear_x = ray.direction[kx] * shear_z;
+    let shear_y = ray.direction[ky] * shear_z;
+
+    let a = vertex0.position - ray.origin;
+    let b = vertex1.position - ray.origin;
+    let c = vertex2.position - ray.origin;
+
+    let ax = a[kx] - shear_x * a[kz];
+    let ay = a[ky] - shear_y * a[kz];
+    let bx = b[kx] - shear_x * b[kz];
+    let by = b[ky] - shear_y * b[kz];
+    let cx = c[kx] - shear_x * c[kz];
+    let cy = c[ky] - shear_y * c[kz];
+
+    // scaled barycentric coordinates
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if((u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0)) {
+        return false;
+    }
+    let determinant = u + v + w;
+    if(determinant == 0.0) {
+        return false;
+    }
+
+    let scaled_distance = u * shear_z * a[kz] + v * shear_z * b[kz] + w * shear_z * c[kz];
+    let distance = scaled_distance / determinant;
+    if(distance <= min_distance || max_distance <= distance) {
+        return false;
+    }
+
+    let barycentric = vec3f(u, v, w) / determinant;
+    let face_normal = normalize(cross(vertex1.position - vertex0.position, vertex2.position - vertex0.position));
+    let normal = barycentric.x * vertex0.normal + barycentric.y * vertex1.normal + barycentric.z * vertex2.normal;
+
+    (*hit_record).distance_from_ray = distance;
+    (*hit_record).point = ray_at_distance(ray, distance);
+    (*hit_record).material = triangle.material;
+    // meshes without normals have zero normals
+    if(length_squared(normal) > 0.0) {
+        (*hit_record).normal = normalize(normal);
+    } else {
+        (*hit_record).normal = face_normal;
+    }
+    return true;
+}
@@ -0,0 +1,3 @@
+// the uniform of the image that both stages read, the rest of the group is only used by the compute shader
+@group(1) @binding(0)
+var<uniform> shared_stage_uniform: SharedStageUniform;
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use eframe::egui;
+use eframe::egui_wgpu::{self, RenderState};
+
+use crate::gpu::{self, DisplaySettings, TimeHistory, WidgetResources};
+use crate::ray_tracer::camera::Camera;
+use crate::ray_tracer::scene::Scene;
+
+/**
+ * How a view shows the scene, given every frame
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct ViewSettings {
+    pub camera: Camera,
+    /// the fraction of the physical pixels of the view that are rendered
+    pub render_scale: f32,
+    pub display: DisplaySettings,
+    /// a sample is added every frame until there are this many of them
+    pub samples_target: u32,
+}
+
+/**
+ * What the gpu gives back to the ui about a view
+ */
+#[derive(Default)]
+struct ViewStatistics {
+    render_times: Arc<Mutex<TimeHistory>>,
+    /// the number of samples added up in the image so far
+    sample_count: Arc<AtomicU32>,
+}
+
+/// every widget has its own resources in the renderer of egui, found with an id no other widget has
+static NEXT_WIDGET_ID: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * Paints ray traced images of a scene in an egui ui.
+ * There can be several widgets, each with its own scene and pipelines.
+ * There can be several views of the scene, each with its own id, camera and image.
+ * The scene is copied for the gpu, it has to be given again with set_scene after modifying it
+ */
+pub struct RayTracerWidget {
+    id: u64,
+    render_state: RenderState,
+    views: HashMap<egui::Id, ViewStatistics>,
+    pub workgroup_size: [u32; 2],
+    /// the copy of the scene handed to the gpu, only updated when the scene changes
+    uploaded_scene: Arc<Scene>,
+    scene_revision: u64,
+    /// the shaders are reloaded when their files change in debug builds
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    shader_watcher: gpu::ShaderWatcher,
+    /// why the last reload failed, the pipelines of the previous shaders are still used
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    shader_errors: Vec<gpu::ShaderError>,
+}
+
+impl RayTracerWidget {
+    /**
+     * Creates the pipelines of the ray tracer in the renderer of egui
+     */
+    pub fn new(render_state: &RenderState, scene: &Scene) -> Self {
+        let id = NEXT_WIDGET_ID.fetch_add(1, Ordering::Relaxed);
+        let resources = gpu::get_render_resources(&render_state.device, render_state.target_format);
+        render_state
+            .renderer
+            .write()
+            .callback_resources
+            .entry::<WidgetResources>()
+            .or_insert_with(WidgetResources::default)
+            .widgets
+            .insert(id, resources);
+        RayTracerWidget {
+            id,
+            render_state: render_state.clone(),
+            views: HashMap::new(),
+            workgroup_size: gpu::DEFAULT_WORKGROUP_SIZE,
+            uploaded_scene: Arc::new(get_uploaded_scene(scene)),
+            scene_revision: gpu::new_scene_revision(),
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            shader_watcher: gpu::ShaderWatcher::default(),
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            shader_errors: Vec::new(),
+        }
+    }
+
+    /**
+     * Has to be called after modifying the scene so the gpu gets the new version.
+     * The cameras and the samples targets are given every frame, they don't need it
+     */
+    pub fn set_scene(&mut self, scene: &Scene) {
+        self.uploaded_scene = Arc::new(get_uploaded_scene(scene));
+        self.scene_revision = gpu::new_scene_revision();
+    }
+
+    /**
+     * Paints the image of the view with this id in the rect.
+     * The view is created the first time it's painted
+     */
+    pub fn paint(&mut self, ui: &egui::Ui, rect: egui::Rect, id: egui::Id, view: &ViewSettings) {
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        self.watch_shaders(ui.ctx());
+        let statistics = self.views.entry(id).or_default();
+        ui.painter().add(egui_wgpu::Callback::new_paint_callback(
+            rect,
+            gpu::RenderCallBack {
+                widget_id: self.id,
+                viewport_id: id,
+                render_times: statistics.render_times.clone(),
+                output_size: rect.size(),
+                pixels_per_point: ui.ctx().pixels_per_point(),
+                render_scale: view.render_scale,
+                workgroup_size: self.workgroup_size,
+                scene: self.uploaded_scene.clone(),
+                scene_revision: self.scene_revision,
+                camera: view.camera,
+                display: view.display,
+                samples_target: view.samples_target,
+                sample_count: statistics.sample_count.clone(),
+                context: ui.ctx().clone(),
+            },
+        ));
+    }
+
+    /**
+     * Reloads the shaders when their files changed, the ui keeps being painted to notice it
+     */
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    fn watch_shaders(&mut self, context: &egui::Context) {
+        context.request_repaint_after(gpu::POLL_INTERVAL);
+        if !self.shader_watcher.poll() {
+            return;
+        }
+        let mut renderer = self.render_state.renderer.write();
+        let Some(resources) = WidgetResources::get_mut(&mut renderer.callback_resources, self.id)
+        else {
+            return;
+        };
+        self.shader_errors = match resources.reload_shaders(&self.render_state.device) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors,
+        };
+    }
+
+    /// why the shaders couldn't be reloaded, empty when the last change worked
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn shader_errors(&self) -> &[gpu::ShaderError] {
+        &self.shader_errors
+    }
+
+    /**
+     * Frees the image of a view that isn't shown anymore
+     */
+    pub fn remove_view(&mut self, id: egui::Id) {
+        self.views.remove(&id);
+        let mut renderer = self.render_state.renderer.write();
+        if let Some(resources) = WidgetResources::get_mut(&mut renderer.callback_resources, self.id)
+        {
+            resources.viewports.remove(&id);
+        }
+    }
+
+    /// the number of samples in the image of a view, 0 before it's painted
+    pub fn sample_count(&self, id: egui::Id) -> u32 {
+        self.views
+            .get(&id)
+            .map_or(0, |view| view.sample_count.load(Ordering::Relaxed))
+    }
+
+    /// the time taken by the last frames of a view, nothing before it's painted
+    pub fn render_times(&self, id: egui::Id) -> Option<MutexGuard<'_, TimeHistory>> {
+        let view = self.views.get(&id)?;
+        Some(view.render_times.lock().unwrap())
+    }
+
+    /**
+     * Copies the image of a view back from the gpu and saves it in the format of the extension of the path
+     */
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_render(&self, id: egui::Id, path: &std::path::Path) -> Result<(), String> {
+        let renderer = self.render_state.renderer.read();
+        let resources = WidgetResources::get(&renderer.callback_resources, self.id)
+            .filter(|resources| resources.viewports.contains_key(&id))
+            .ok_or("nothing was rendered yet")?;
+        crate::export::save_render(
+            path,
+            &self.render_state.device,
+            &self.render_state.queue,
+            &resources.shared,
+            &resources.viewports[&id],
+        )
+    }
+}
+
+impl Drop for RayTracerWidget {
+    /// the pipelines and the images of the widget are freed with it
+    fn drop(&mut self) {
+        let mut renderer = self.render_state.renderer.write();
+        if let Some(resources) = renderer.callback_resources.get_mut::<WidgetResources>() {
+            resources.widgets.remove(&self.id);
+        }
+    }
+}
+
+/**
+ * The copy of the scene given to the gpu, with its bvh rebuilt in case the spheres or instances changed
+ */
+fn get_uploaded_scene(scene: &Scene) -> Scene {
+    let mut scene = scene.clone();
+    scene.rebuild_bvh();
+    scene
+}